@@ -0,0 +1,35 @@
+/* Compare diagnostics with a lone literal, which skip the formatter, against
+   formatted ones printing the same message.  Run with “cargo bench”. */
+
+use std::hint::black_box;
+use std::time::Instant;
+
+const N: u32 = 1_000_000;
+
+fn bench(name: &str, f: impl Fn()) {
+	let start = Instant::now();
+	for _ in 0..N {
+		f();
+	}
+	let ns = start.elapsed().as_nanos() as f64 / f64::from(N);
+	println!("{name:<18} {ns:>8.1} ns/iter");
+}
+
+fn main() {
+	let buf = cerm::__capture();
+	cerm::set_progname("prog");
+
+	cerm::warn!("disk almost full");
+	let lit = buf.take();
+	cerm::warn!("{}", "disk almost full");
+	assert_eq!(buf.take(), lit);
+
+	bench("warn!(lit)", || {
+		cerm::warn!("disk almost full");
+		black_box(buf.borrow_mut()).clear();
+	});
+	bench("warn!(\"{}\", lit)", || {
+		cerm::warn!("{}", black_box("disk almost full"));
+		black_box(buf.borrow_mut()).clear();
+	});
+}
@@ -0,0 +1,1734 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
+use std::panic;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard};
+
+use crate::Diagnostic;
+
+/// The line terminator written at the end of every diagnostic.
+///
+/// The default is [`LineEnding::Lf`], which matches the behaviour of
+/// [`eprintln!`].  Use [`set_line_ending()`] to change it.  The line
+/// terminator is only written if the [`record_terminator()`] is
+/// [`RecordTerminator::Newline`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LineEnding {
+	/// Terminate diagnostics with `"\n"`.
+	#[default]
+	Lf,
+	/// Terminate diagnostics with `"\r\n"`.
+	Crlf,
+	/// Terminate diagnostics with `"\r\n"` on Windows and `"\n"` elsewhere.
+	Platform,
+}
+
+impl LineEnding {
+	/// Return the terminator as a string.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Lf => "\n",
+			Self::Crlf => "\r\n",
+			Self::Platform if cfg!(windows) => "\r\n",
+			Self::Platform => "\n",
+		}
+	}
+
+	fn from_u8(n: u8) -> Self {
+		match n {
+			1 => Self::Crlf,
+			2 => Self::Platform,
+			_ => Self::Lf,
+		}
+	}
+}
+
+static LINE_ENDING: AtomicU8 = AtomicU8::new(LineEnding::Lf as u8);
+
+/// Set the line terminator written at the end of every diagnostic.
+///
+/// This affects all the macros in this crate, and applies to all threads.
+///
+/// # Examples
+///
+/// Terminate all diagnostics with a carriage return and a newline.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{warn, LineEnding};
+///
+/// cerm::set_line_ending(LineEnding::Crlf);
+/// warn!("done");
+/// assert_eq!(buf.take(), b"prog: done\r\n");
+///
+/// cerm::set_line_ending(LineEnding::Platform);
+/// warn!("done");
+/// assert_eq!(buf.take(), if cfg!(windows) { &b"prog: done\r\n"[..] } else { b"prog: done\n" });
+/// ```
+pub fn set_line_ending(le: LineEnding) {
+	LINE_ENDING.store(le as u8, Ordering::Relaxed);
+}
+
+/// Get the line terminator written at the end of every diagnostic.
+///
+/// See [`set_line_ending()`].
+pub fn line_ending() -> LineEnding {
+	LineEnding::from_u8(LINE_ENDING.load(Ordering::Relaxed))
+}
+
+/// What is written at the end of every diagnostic.
+///
+/// The default is [`RecordTerminator::Newline`].  Use
+/// [`set_record_terminator()`] to change it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecordTerminator {
+	/// Terminate diagnostics with the current [`line_ending()`].
+	#[default]
+	Newline,
+	/// Terminate diagnostics with a NUL byte, as expected by tools such as
+	/// `xargs -0`.
+	Nul,
+	/// Don’t terminate diagnostics at all, leaving it up to the sink to frame
+	/// them.
+	None,
+}
+
+impl RecordTerminator {
+	/// Return the terminator as a string.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Newline => line_ending().as_str(),
+			Self::Nul => "\0",
+			Self::None => "",
+		}
+	}
+
+	fn from_u8(n: u8) -> Self {
+		match n {
+			1 => Self::Nul,
+			2 => Self::None,
+			_ => Self::Newline,
+		}
+	}
+}
+
+static RECORD_TERMINATOR: AtomicU8 = AtomicU8::new(RecordTerminator::Newline as u8);
+
+/// Set what is written at the end of every diagnostic.
+///
+/// This is useful for machine-consumed output and for sinks doing their own
+/// framing, which don’t want diagnostics to be terminated by a newline.  It
+/// affects all the macros in this crate, and applies to all threads.
+///
+/// # Examples
+///
+/// Separate diagnostics with NUL bytes.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{warn, RecordTerminator};
+///
+/// cerm::set_record_terminator(RecordTerminator::Nul);
+/// warn!("first");
+/// warn!("second\nline");
+/// assert_eq!(buf.take(), b"prog: first\0prog: second\nline\0");
+/// ```
+pub fn set_record_terminator(rt: RecordTerminator) {
+	RECORD_TERMINATOR.store(rt as u8, Ordering::Relaxed);
+}
+
+/// Get what is written at the end of every diagnostic.
+///
+/// See [`set_record_terminator()`].
+pub fn record_terminator() -> RecordTerminator {
+	RecordTerminator::from_u8(RECORD_TERMINATOR.load(Ordering::Relaxed))
+}
+
+type FlushHook = Box<dyn Fn() + Send + Sync>;
+
+static FLUSH_HOOK: Mutex<Option<FlushHook>> = Mutex::new(None);
+
+/// Set a hook to run right before [`err_code!`](crate::err_code!) exits the program.
+///
+/// Asynchronous or buffered loggers may still have pending writes when a fatal
+/// diagnostic is reported, and [`std::process::exit()`] does not wait for them.
+/// This hook is the place to flush them, for example by calling
+/// `runtime.block_on(flush())` or by dropping a logger’s worker guard.
+///
+/// The hook must complete synchronously; the program exits as soon as it
+/// returns.  It is also run when the fatal diagnostic panics instead of
+/// exiting, such as under [`catch_fatal()`].  Setting a new hook replaces the
+/// previous one.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::{self, Write};
+///
+/// cerm::set_async_flush_hook(Box::new(|| {
+/// 	let _ = io::stdout().flush();
+/// }));
+/// cerm::err!("this is printed, stdout is flushed, and then we exit");
+/// ```
+///
+/// The hook runs before the [`FatalExit`] is caught.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+///
+/// static FLUSHED: AtomicBool = AtomicBool::new(false);
+///
+/// cerm::set_async_flush_hook(Box::new(|| FLUSHED.store(true, Ordering::Relaxed)));
+/// assert_eq!(cerm::catch_fatal(|| 42), Ok(42));
+/// assert!(!FLUSHED.load(Ordering::Relaxed));
+///
+/// let res = cerm::catch_fatal(|| { cerm::err!("giving up"); });
+/// assert!(res.is_err());
+/// assert!(FLUSHED.load(Ordering::Relaxed));
+/// ```
+pub fn set_async_flush_hook(hook: FlushHook) {
+	*FLUSH_HOOK.lock().unwrap_or_else(PoisonError::into_inner) = Some(hook);
+}
+
+pub(crate) fn run_flush_hook() {
+	if let Some(hook) = &*FLUSH_HOOK.lock().unwrap_or_else(PoisonError::into_inner) {
+		hook();
+	}
+}
+
+static ABORT_ON_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Make [`err_code!`](crate::err_code!) abort the program instead of exiting.
+///
+/// When enabled, fatal diagnostics end the program with
+/// [`std::process::abort()`] instead of [`std::process::exit()`], which raises
+/// `SIGABRT` on Unix and so produces a core dump if the system is configured to
+/// do so.  This is useful for inspecting the state of a program that died in
+/// CI.  The exit code given to [`err_code!`](crate::err_code!) is ignored.
+///
+/// The hook set with [`set_async_flush_hook()`] still runs before aborting.
+/// This setting has no effect under [`ExitBehavior::Panic`].
+///
+/// # Examples
+///
+/// Abort if the `MYTOOL_ABORT` environment variable is set.
+///
+/// ```
+/// cerm::set_abort_on_error(std::env::var_os("MYTOOL_ABORT").is_some());
+/// ```
+pub fn set_abort_on_error(abort: bool) {
+	ABORT_ON_ERROR.store(abort, Ordering::Relaxed);
+}
+
+pub(crate) fn abort_on_error() -> bool {
+	ABORT_ON_ERROR.load(Ordering::Relaxed)
+}
+
+/// An alert given to the user when a fatal diagnostic is printed.
+///
+/// The default is [`AlertStyle::None`].  Use [`set_alert_on_error()`] to change
+/// it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AlertStyle {
+	/// Give no alert.
+	#[default]
+	None,
+	/// Ring the terminal bell.
+	Bell,
+	/// Flash the terminal screen.
+	VisualBell,
+}
+
+impl AlertStyle {
+	pub(crate) fn as_str(self) -> &'static str {
+		match self {
+			Self::None => "",
+			Self::Bell => "\x07",
+			Self::VisualBell => "\x1b[?5h\x1b[?5l",
+		}
+	}
+
+	fn from_u8(n: u8) -> Self {
+		match n {
+			1 => Self::Bell,
+			2 => Self::VisualBell,
+			_ => Self::None,
+		}
+	}
+}
+
+static ALERT_STYLE: AtomicU8 = AtomicU8::new(AlertStyle::None as u8);
+
+/// Alert the user when [`err_code!`](crate::err_code!) prints a fatal diagnostic.
+///
+/// This is handy for long-running interactive programs, where the user might
+/// not be looking at the terminal when the program dies.  The alert is written
+/// right before the diagnostic, in the same write, but only if the diagnostic
+/// is printed to the standard error and the standard error is a terminal.
+/// Diagnostics sent somewhere else, such as to the sink of
+/// [`set_local_sink()`] or with [`set_all_to_stdout()`], are never preceded by
+/// an alert.
+///
+/// # Examples
+///
+/// Ring the terminal bell on fatal errors.
+///
+/// ```
+/// use cerm::AlertStyle;
+///
+/// cerm::set_alert_on_error(AlertStyle::Bell);
+/// ```
+pub fn set_alert_on_error(style: AlertStyle) {
+	ALERT_STYLE.store(style as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn alert_on_error() -> AlertStyle {
+	AlertStyle::from_u8(ALERT_STYLE.load(Ordering::Relaxed))
+}
+
+/// What the [`err!`](crate::err!) family of macros does after printing a diagnostic.
+///
+/// The default is [`ExitBehavior::Exit`], or [`ExitBehavior::Panic`] if the
+/// `panic_exit` feature is enabled.  Use [`set_exit_behavior()`] to change it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitBehavior {
+	/// Exit the program with [`std::process::exit()`].
+	#[cfg_attr(not(feature = "panic_exit"), default)]
+	Exit,
+	/// Panic with a [`FatalExit`] payload instead of exiting.
+	#[cfg_attr(feature = "panic_exit", default)]
+	Panic,
+}
+
+impl ExitBehavior {
+	fn from_u8(n: u8) -> Self {
+		match n {
+			1 => Self::Panic,
+			_ => Self::Exit,
+		}
+	}
+}
+
+static EXIT_BEHAVIOR: AtomicU8 = AtomicU8::new(if cfg!(feature = "panic_exit") {
+	ExitBehavior::Panic as u8
+} else {
+	ExitBehavior::Exit as u8
+});
+
+/// Set what the [`err!`](crate::err!) family of macros does after printing a diagnostic.
+///
+/// This applies to all threads, except those which have set their own with
+/// [`set_thread_exit_behavior()`].  Setting [`ExitBehavior::Panic`] is useful
+/// in programs running code that must not exit the whole process, such as
+/// plugins.  Tests should use [`set_thread_exit_behavior()`] or
+/// [`catch_fatal()`] instead, so that they don’t affect each other.  Such programs may prefer to enable the `panic_exit` feature,
+/// which makes [`ExitBehavior::Panic`] the default.  Either way, panicking
+/// only lets the caller recover if the program is built with
+/// `panic = "unwind"`, which is the default.
+///
+/// # Examples
+///
+/// Catch a fatal diagnostic and inspect its exit code and message.
+///
+/// ```
+/// use std::panic;
+/// use cerm::{err_code, ExitBehavior, FatalExit};
+///
+/// cerm::set_exit_behavior(ExitBehavior::Panic);
+/// let res = panic::catch_unwind(|| { err_code!(2, "bad input"); });
+/// let fe = res.unwrap_err().downcast::<FatalExit>().unwrap();
+/// assert_eq!(fe.code, 2);
+/// assert_eq!(fe.message, "bad input");
+/// ```
+///
+/// With the `panic_exit` feature, a host program can catch the fatal
+/// diagnostics of the code it runs without any setup.
+///
+/// ```
+/// # #[cfg(feature = "panic_exit")] {
+/// use std::panic;
+/// use cerm::FatalExit;
+///
+/// let plugin = || { cerm::err_code!(3, "plugin failed"); };
+/// let fe = panic::catch_unwind(plugin).unwrap_err().downcast::<FatalExit>().unwrap();
+/// assert_eq!(fe.code, 3);
+/// # }
+/// ```
+pub fn set_exit_behavior(eb: ExitBehavior) {
+	EXIT_BEHAVIOR.store(eb as u8, Ordering::Relaxed);
+}
+
+thread_local! {
+	static THREAD_EXIT_BEHAVIOR: Cell<Option<ExitBehavior>> = const { Cell::new(None) };
+}
+
+/// Set what the [`err!`] family of macros does after printing a diagnostic on
+/// the current thread.
+///
+/// This is the same thing as [`set_exit_behavior()`], but only for the current
+/// thread, and it takes precedence over the behavior set with
+/// [`set_exit_behavior()`].  Passing [`None`] removes the override.  Tests
+/// should prefer this function, as the test harness runs them on several
+/// threads of the same process.
+///
+/// # Examples
+///
+/// ```
+/// use std::{panic, thread};
+/// use cerm::{err, ExitBehavior, FatalExit};
+///
+/// cerm::set_thread_exit_behavior(Some(ExitBehavior::Panic));
+/// let res = panic::catch_unwind(|| { err!("bad input"); });
+/// assert_eq!(res.unwrap_err().downcast::<FatalExit>().unwrap().code, 1);
+///
+/// /* Other threads are not affected */
+/// # #[cfg(not(feature = "panic_exit"))]
+/// thread::spawn(|| assert_eq!(cerm::exit_behavior(), ExitBehavior::Exit))
+/// 	.join()
+/// 	.unwrap();
+///
+/// cerm::set_thread_exit_behavior(None);
+/// # #[cfg(not(feature = "panic_exit"))]
+/// assert_eq!(cerm::exit_behavior(), ExitBehavior::Exit);
+/// ```
+///
+/// [`err!`]: crate::err!
+pub fn set_thread_exit_behavior(eb: Option<ExitBehavior>) {
+	THREAD_EXIT_BEHAVIOR.set(eb);
+}
+
+/// Get what the [`err!`](crate::err!) family of macros does after printing a
+/// diagnostic on the current thread.
+///
+/// This is the behavior set with [`set_thread_exit_behavior()`] if any, and
+/// otherwise the one set with [`set_exit_behavior()`].
+pub fn exit_behavior() -> ExitBehavior {
+	THREAD_EXIT_BEHAVIOR
+		.get()
+		.unwrap_or_else(|| ExitBehavior::from_u8(EXIT_BEHAVIOR.load(Ordering::Relaxed)))
+}
+
+/// The panic payload of a fatal diagnostic under [`ExitBehavior::Panic`].
+///
+/// The payload can be recovered with [`std::panic::catch_unwind()`] and
+/// [`Box::downcast()`]; see [`set_exit_behavior()`] for an example.
+///
+/// When displayed, the exit code is annotated with its name if it is one of
+/// those defined by `<sysexits.h>`; see
+/// [`describe_exit_code()`](crate::describe_exit_code).
+///
+/// ```
+/// use cerm::FatalExit;
+///
+/// let fe = FatalExit { code: 64, message: "usage: mytool file".into() };
+/// assert_eq!(fe.to_string(), "usage: mytool file (exit code 64, EX_USAGE)");
+/// let fe = FatalExit { code: 3, message: "oops".into() };
+/// assert_eq!(fe.to_string(), "oops (exit code 3)");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FatalExit {
+	/// The code the program would have exited with.
+	pub code: i32,
+	/// The diagnostic message, without the program name prefix.
+	pub message: String,
+}
+
+impl fmt::Display for FatalExit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} (exit code {}", self.message, self.code)?;
+		if let Some(name) = crate::describe_exit_code(self.code) {
+			write!(f, ", {name}")?;
+		}
+		f.write_str(")")
+	}
+}
+
+thread_local! {
+	static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run a closure, turning fatal diagnostics into errors.
+///
+/// If the closure invokes a macro from the [`err!`](crate::err!) family, the
+/// diagnostic is printed and the hook set with [`set_async_flush_hook()`] is
+/// run as usual, but instead of exiting, the current thread panics with a
+/// [`FatalExit`] which is caught and returned as an error; otherwise the
+/// closure’s return value is returned.  Other panics are propagated as usual.
+///
+/// This makes it possible to reuse code written with [`err!`](crate::err!) as a
+/// library without terminating the process.  Only the current thread is
+/// affected: the [`exit_behavior()`] is left alone, so other threads reporting
+/// fatal diagnostics while the closure runs still exit or panic as configured.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::{err_code, ExitBehavior, FatalExit};
+///
+/// let res = cerm::catch_fatal(|| { err_code!(3, "oops"); });
+/// assert!(matches!(res, Err(FatalExit { code: 3, .. })));
+///
+/// assert_eq!(cerm::catch_fatal(|| 42), Ok(42));
+/// # #[cfg(not(feature = "panic_exit"))]
+/// assert_eq!(cerm::exit_behavior(), ExitBehavior::Exit);
+/// ```
+///
+/// Catching fatal diagnostics on one thread doesn’t affect the others.
+///
+/// ```
+/// use std::sync::Barrier;
+/// use std::thread;
+/// use cerm::{err, ExitBehavior};
+///
+/// let barrier = Barrier::new(2);
+/// thread::scope(|s| {
+/// 	s.spawn(|| {
+/// 		let res = cerm::catch_fatal(|| {
+/// 			barrier.wait();
+/// 			barrier.wait();
+/// 			err!("caught");
+/// 		});
+/// 		assert_eq!(res.unwrap_err().message, "caught");
+/// 	});
+/// 	barrier.wait();
+/// 	/* The other thread is running catch_fatal() right now */
+/// 	# #[cfg(not(feature = "panic_exit"))]
+/// 	assert_eq!(cerm::exit_behavior(), ExitBehavior::Exit);
+/// 	barrier.wait();
+/// });
+/// ```
+pub fn catch_fatal<R>(f: impl FnOnce() -> R) -> Result<R, FatalExit> {
+	struct Restore(bool);
+
+	impl Drop for Restore {
+		fn drop(&mut self) {
+			CATCHING.with(|c| c.set(self.0));
+		}
+	}
+
+	let _restore = Restore(CATCHING.with(|c| c.replace(true)));
+	match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+		Ok(v) => Ok(v),
+		Err(e) => match e.downcast::<FatalExit>() {
+			Ok(fe) => Err(*fe),
+			Err(e) => panic::resume_unwind(e),
+		},
+	}
+}
+
+/* Return whether the current thread is running a closure in catch_fatal() */
+pub(crate) fn catching() -> bool {
+	CATCHING.with(Cell::get)
+}
+
+static HYPERLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Render file paths in diagnostics as terminal hyperlinks.
+///
+/// When enabled, the paths printed by [`warn_file!`](crate::warn_file!) and [`err_file!`](crate::err_file!) are
+/// wrapped in an [OSC 8] escape sequence linking to the file, which terminals
+/// supporting it render as a clickable link.  The paths are printed as plain
+/// text when the standard error is not a terminal.  This is disabled by
+/// default.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::__assume_terminal();
+/// # cerm::set_progname("prog");
+/// cerm::set_hyperlinks(true);
+/// cerm::warn_file!("src/main.rs", "unused variable");
+/// # let url = std::path::absolute("src/main.rs").unwrap();
+/// # let url = url.to_str().unwrap();
+/// # let want = format!(
+/// # 	"prog: \x1b]8;;file://{url}\x1b\\src/main.rs\x1b]8;;\x1b\\: unused variable\n",
+/// # );
+/// # assert_eq!(buf.take(), want.as_bytes());
+/// ```
+///
+/// Only the paths themselves are linked.  Escapes in the rest of the message
+/// or in the path are sanitized as usual, so a message can’t forge a link.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::__assume_terminal();
+/// # cerm::set_progname("prog");
+/// cerm::set_hyperlinks(true);
+/// let name = "\x1b]8;;https://example.com\x1b\\notes.txt\x1b]8;;\x1b\\";
+/// cerm::warn!("{name}: file not found");
+/// assert_eq!(
+/// 	buf.take(),
+/// 	b"prog: \\x1b]8;;https://example.com\\x1b\\notes.txt\\x1b]8;;\\x1b\\: file not found\n",
+/// );
+///
+/// cerm::warn_file!("\x1b[2J.txt", "unused variable");
+/// let out = String::from_utf8(buf.take()).unwrap();
+/// assert!(out.ends_with("%1B%5B2J.txt\x1b\\\\x1b[2J.txt\x1b]8;;\x1b\\: unused variable\n"));
+/// ```
+///
+/// [OSC 8]: https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
+pub fn set_hyperlinks(enable: bool) {
+	HYPERLINKS.store(enable, Ordering::Relaxed);
+}
+
+pub(crate) fn hyperlinks() -> bool {
+	HYPERLINKS.load(Ordering::Relaxed)
+}
+
+static LABEL_PREFIX: RwLock<String> = RwLock::new(String::new());
+
+/// Set a label to print before the program name of every diagnostic.
+///
+/// The label is printed verbatim and followed by a space, so that with a label
+/// of `"[host42]"` diagnostics take the form `"[host42] progname: message"`.
+/// This is useful to correlate the output of many copies of the same program,
+/// for example by labelling each diagnostic with the hostname.  An empty label
+/// — the default — prints nothing.
+///
+/// # Examples
+///
+/// ```
+/// cerm::set_label_prefix("[host42]");
+/// cerm::warn!("this is labelled");
+/// ```
+pub fn set_label_prefix(label: impl Into<String>) {
+	*LABEL_PREFIX.write().unwrap_or_else(PoisonError::into_inner) = label.into();
+}
+
+pub(crate) fn label_prefix() -> RwLockReadGuard<'static, String> {
+	LABEL_PREFIX.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+static PROGNAME: RwLock<Option<Cow<'static, str>>> = RwLock::new(None);
+
+/// Set the program name printed before every diagnostic.
+///
+/// By default the program name is the first element of [`std::env::args`],
+/// which is normally the path the program was invoked with, unless it is
+/// overridden by an environment variable as described in
+/// [`set_progname_env_var()`].  This function overrides both for all threads,
+/// though a thread can still set its own name with [`set_thread_progname()`].
+///
+/// The given name is converted into an owned [`String`], which allocates.  If
+/// the name is a string literal or otherwise lives for the entire program, you
+/// may prefer [`set_static_progname()`] which doesn’t copy it.
+///
+/// # Examples
+///
+/// ```
+/// let name = std::env::var("MYTOOL_NAME").unwrap_or("mytool".into());
+/// cerm::set_progname(name);
+/// ```
+pub fn set_progname(name: impl Into<String>) {
+	let name = Cow::Owned(name.into());
+	*PROGNAME.write().unwrap_or_else(PoisonError::into_inner) = Some(name);
+}
+
+/// Set the program name printed before every diagnostic, without copying it.
+///
+/// This is the same thing as [`set_progname()`], but the name is borrowed for
+/// the rest of the program instead of copied, so setting it doesn’t allocate.
+/// Warnings are rendered into a buffer which each thread keeps around, so once
+/// the first one is printed, printing a warning with a literal message and the
+/// default settings doesn’t allocate either.  Use this function when the name is a string literal, and
+/// [`set_progname()`] when it is computed at runtime.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// cerm::set_static_progname("mytool");
+/// cerm::warn!("hello");
+/// # assert_eq!(*buf.borrow(), b"mytool: hello\n");
+/// ```
+pub fn set_static_progname(name: &'static str) {
+	let name = Cow::Borrowed(name);
+	*PROGNAME.write().unwrap_or_else(PoisonError::into_inner) = Some(name);
+}
+
+pub(crate) fn progname() -> RwLockReadGuard<'static, Option<Cow<'static, str>>> {
+	PROGNAME.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+thread_local! {
+	static THREAD_PROGNAME: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Set the program name printed before the diagnostics of the current thread.
+///
+/// This is the same thing as [`set_progname()`], but only for the current
+/// thread, and it takes precedence over the name set with [`set_progname()`].
+/// The name stays in effect for the lifetime of the thread, or until it is set
+/// again.  This is useful to label the diagnostics of worker threads with the
+/// task they are running.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Barrier};
+/// use std::thread;
+///
+/// # let buf = cerm::__capture();
+/// cerm::set_progname("mytool");
+/// let barrier = Arc::new(Barrier::new(2));
+/// let workers: Vec<_> = (0..2)
+/// 	.map(|i| {
+/// 		let barrier = barrier.clone();
+/// 		thread::spawn(move || {
+/// 			# let buf = cerm::__capture();
+/// 			cerm::set_thread_progname(format!("worker-{i}"));
+/// 			/* Both names are set before either thread prints */
+/// 			barrier.wait();
+/// 			cerm::warn!("timed out");
+/// 			assert_eq!(buf.take(), format!("worker-{i}: timed out\n").as_bytes());
+/// 		})
+/// 	})
+/// 	.collect();
+/// for w in workers {
+/// 	w.join().unwrap();
+/// }
+///
+/// cerm::warn!("all workers finished");
+/// assert_eq!(buf.take(), b"mytool: all workers finished\n");
+/// ```
+pub fn set_thread_progname(name: impl Into<String>) {
+	THREAD_PROGNAME.set(Some(name.into()));
+}
+
+/* Call f with the program name of the current thread, if any */
+pub(crate) fn with_thread_progname<R>(f: impl FnOnce(Option<&str>) -> R) -> R {
+	THREAD_PROGNAME.with_borrow(|name| f(name.as_deref()))
+}
+
+static PROGNAME_ENV_VAR: RwLock<Cow<'static, str>> = RwLock::new(Cow::Borrowed("CERM_PROGNAME"));
+
+/// Set the environment variable overriding the default program name.
+///
+/// If the environment variable with the given name is set, its value is used
+/// as the program name instead of the first element of [`std::env::args`].
+/// This is useful for wrapper scripts which can’t easily change the `argv[0]`
+/// of the program they execute.  The variable is only read once, when the
+/// first diagnostic after setting it is printed.  A name set with
+/// [`set_progname()`] or [`set_static_progname()`] still takes precedence over
+/// the environment variable.  The default environment variable is
+/// `CERM_PROGNAME`.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// use std::env;
+///
+/// cerm::set_progname_env_var("MYTOOL_NAME");
+/// env::set_var("MYTOOL_NAME", "mytool-wrapper");
+/// cerm::warn!("hello");
+/// assert_eq!(buf.take(), b"mytool-wrapper: hello\n");
+///
+/// env::set_var("MYTOOL_NAME", "ignored");
+/// cerm::warn!("hello");
+/// assert_eq!(buf.take(), b"mytool-wrapper: hello\n");
+///
+/// cerm::set_progname("mytool");
+/// cerm::warn!("hello");
+/// assert_eq!(buf.take(), b"mytool: hello\n");
+/// ```
+pub fn set_progname_env_var(var: impl Into<String>) {
+	let var = Cow::Owned(var.into());
+	let mut cur = PROGNAME_ENV_VAR.write().unwrap_or_else(PoisonError::into_inner);
+	*cur = var;
+	*ENV_PROGNAME.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/* The value of the environment variable named by PROGNAME_ENV_VAR, or None if
+   it hasn’t been read yet */
+static ENV_PROGNAME: RwLock<Option<Option<String>>> = RwLock::new(None);
+
+/* Call f with the program name given in the environment, if any */
+pub(crate) fn with_env_progname<R>(f: impl FnOnce(Option<&str>) -> R) -> R {
+	let name = ENV_PROGNAME.read().unwrap_or_else(PoisonError::into_inner);
+	if let Some(name) = &*name {
+		return f(name.as_deref());
+	}
+	drop(name);
+	let var = PROGNAME_ENV_VAR.read().unwrap_or_else(PoisonError::into_inner);
+	let mut name = ENV_PROGNAME.write().unwrap_or_else(PoisonError::into_inner);
+	f(name.get_or_insert_with(|| env::var(&**var).ok()).as_deref())
+}
+
+thread_local! {
+	static LOCAL_SINK: RefCell<Option<Box<dyn Write>>> = const { RefCell::new(None) };
+	static SINK_FAILED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Write the diagnostics of the current thread to the given writer.
+///
+/// Diagnostics that would be printed to the standard error by the current
+/// thread are written to `w` instead.  This only affects the current thread;
+/// other threads keep printing to the standard error.  As the writer never
+/// leaves the current thread, it doesn’t need to implement [`Send`], which makes
+/// this useful for capturing diagnostics in tests.  Setting a new sink replaces
+/// the previous one, which can be removed with [`take_local_sink()`].
+///
+/// If writing to the sink fails, the diagnostic is printed to the standard error
+/// instead so that it isn’t lost.  The first such failure of a sink is also
+/// reported with a diagnostic of its own.
+///
+/// # Examples
+///
+/// Capture diagnostics in a buffer shared with an [`Rc`](std::rc::Rc).
+///
+/// ```
+/// use std::cell::RefCell;
+/// use std::io::{self, Write};
+/// use std::rc::Rc;
+///
+/// struct Capture(Rc<RefCell<Vec<u8>>>);
+///
+/// impl Write for Capture {
+/// 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+/// 		self.0.borrow_mut().write(buf)
+/// 	}
+///
+/// 	fn flush(&mut self) -> io::Result<()> {
+/// 		Ok(())
+/// 	}
+/// }
+///
+/// let buf = Rc::new(RefCell::new(Vec::new()));
+/// cerm::set_label_prefix("[test]");
+/// cerm::set_local_sink(Capture(buf.clone()));
+/// cerm::warn!("captured {}", 42);
+/// assert!(cerm::take_local_sink().is_some());
+/// cerm::warn!("not captured");
+///
+/// let buf = buf.borrow();
+/// let s = String::from_utf8_lossy(&buf);
+/// assert!(s.starts_with("[test] "));
+/// assert!(s.ends_with(": captured 42\n"));
+/// ```
+///
+/// A sink that fails doesn’t lose diagnostics.
+///
+/// ```
+/// use std::io::{self, Write};
+///
+/// struct Full;
+///
+/// impl Write for Full {
+/// 	fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+/// 		Err(io::Error::from(io::ErrorKind::StorageFull))
+/// 	}
+///
+/// 	fn flush(&mut self) -> io::Result<()> {
+/// 		Ok(())
+/// 	}
+/// }
+///
+/// cerm::set_local_sink(Full);
+/// cerm::warn!("this is printed to the standard error");
+/// ```
+pub fn set_local_sink(w: impl Write + 'static) {
+	LOCAL_SINK.with(|sink| *sink.borrow_mut() = Some(Box::new(w)));
+	SINK_FAILED.with(|f| f.set(false));
+}
+
+/// Remove the writer set with [`set_local_sink()`] and return it.
+///
+/// Diagnostics of the current thread are printed to the standard error again.
+pub fn take_local_sink() -> Option<Box<dyn Write>> {
+	LOCAL_SINK.with(|sink| sink.borrow_mut().take())
+}
+
+/* The sink used by the examples to check what is printed */
+
+#[doc(hidden)]
+pub struct __Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for __Capture {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.borrow_mut().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[doc(hidden)]
+pub fn __capture() -> Rc<RefCell<Vec<u8>>> {
+	let buf = Rc::new(RefCell::new(Vec::new()));
+	set_local_sink(__Capture(buf.clone()));
+	buf
+}
+
+/* Write a string to the local sink, returning None if there is none */
+pub(crate) fn write_local_sink(s: &str) -> Option<io::Result<()>> {
+	LOCAL_SINK.with(|sink| {
+		/* The sink may itself be printing a diagnostic, in which case we can’t
+		   borrow it again */
+		match sink.try_borrow_mut().as_deref_mut() {
+			Ok(Some(w)) => Some(w.write_all(s.as_bytes()).and_then(|()| w.flush())),
+			_ => None,
+		}
+	})
+}
+
+/* Return whether the current thread has a local sink */
+pub(crate) fn has_local_sink() -> bool {
+	LOCAL_SINK.with(|sink| sink.try_borrow().map_or(true, |w| w.is_some()))
+}
+
+/* Record that the local sink failed, returning whether it is the first time */
+pub(crate) fn local_sink_failed() -> bool {
+	!SINK_FAILED.with(|f| f.replace(true))
+}
+
+/* 0 means that sanitization depends on whether the standard error is a
+   terminal */
+static SANITIZE: AtomicU8 = AtomicU8::new(0);
+
+/// Escape control characters in diagnostic messages.
+///
+/// Diagnostics often include untrusted data such as file names or responses
+/// from a server, which may contain escape sequences that a terminal would
+/// interpret — clearing the screen, changing the window title, and worse.  When
+/// enabled, control characters in the message (other than newlines and tabs)
+/// are printed as escapes such as `\x1b` instead.  The program name and label
+/// are not affected.
+///
+/// By default, messages are sanitized if and only if the standard error is a
+/// terminal, so that piped output stays byte-for-byte identical.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// cerm::set_static_progname("mytool");
+/// cerm::set_sanitize(true);
+/// cerm::warn!("{}: file not found", "\x1b[2Jevil");
+/// # assert_eq!(*buf.borrow(), b"mytool: \\x1b[2Jevil: file not found\n");
+/// ```
+pub fn set_sanitize(enable: bool) {
+	SANITIZE.store(if enable { 1 } else { 2 }, Ordering::Relaxed);
+}
+
+/* Return whether to sanitize messages, or None if it wasn’t configured */
+pub(crate) fn sanitize() -> Option<bool> {
+	match SANITIZE.load(Ordering::Relaxed) {
+		1 => Some(true),
+		2 => Some(false),
+		_ => None,
+	}
+}
+
+/// The number of distinct diagnostics remembered by [`set_dedup_messages()`].
+pub const DEDUP_CAPACITY: usize = 256;
+
+static DEDUP: AtomicBool = AtomicBool::new(false);
+static RECENT: Mutex<VecDeque<u64>> = Mutex::new(VecDeque::new());
+
+/// Suppress non-fatal diagnostics identical to ones already printed.
+///
+/// When enabled, every rendered diagnostic — including the program name — is
+/// compared against those printed before it, and exact repeats are not
+/// printed again, no matter where they come from.  Fatal diagnostics are always
+/// printed.  This is disabled by default.
+///
+/// To bound memory usage, only hashes of the [`DEDUP_CAPACITY`] most recently
+/// seen distinct diagnostics are remembered; a diagnostic that has not been
+/// seen for longer than that is printed again.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// fn load() { cerm::warn!("cache is stale"); }
+/// fn save() { cerm::warn!("cache is stale"); }
+///
+/// cerm::set_dedup_messages(true);
+/// load();
+/// save();
+/// # assert_eq!(buf.borrow().iter().filter(|&&b| b == b'\n').count(), 1);
+/// ```
+pub fn set_dedup_messages(enable: bool) {
+	DEDUP.store(enable, Ordering::Relaxed);
+	if !enable {
+		RECENT.lock().unwrap_or_else(PoisonError::into_inner).clear();
+	}
+}
+
+/* Return whether deduplication is enabled and the given diagnostic was printed
+   recently, remembering it if not */
+pub(crate) fn is_repeat(s: &str) -> bool {
+	if !DEDUP.load(Ordering::Relaxed) {
+		return false;
+	}
+
+	let mut h = DefaultHasher::new();
+	s.hash(&mut h);
+	let h = h.finish();
+
+	let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+	let repeat = match recent.iter().position(|&x| x == h) {
+		Some(i) => {
+			recent.remove(i);
+			true
+		}
+		None => false,
+	};
+	recent.push_back(h);
+	if recent.len() > DEDUP_CAPACITY {
+		recent.pop_front();
+	}
+	repeat
+}
+
+static ALL_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// Print all diagnostics to the standard output instead of the standard error.
+///
+/// This is meant for simple tools that deliberately print everything to the
+/// standard output.  It applies to every diagnostic, including those printed
+/// by [`err!`], and has no effect while a sink set with [`set_local_sink()`]
+/// is installed.  As on the standard error, diagnostics written to a pipe
+/// whose reading end was closed are dropped.  This is disabled by default.
+///
+/// # Examples
+///
+/// ```
+/// cerm::set_all_to_stdout(true);
+/// cerm::warn!("this is printed to the standard output");
+/// ```
+///
+/// [`err!`]: crate::err!
+pub fn set_all_to_stdout(enable: bool) {
+	ALL_TO_STDOUT.store(enable, Ordering::Relaxed);
+}
+
+pub(crate) fn all_to_stdout() -> bool {
+	ALL_TO_STDOUT.load(Ordering::Relaxed)
+}
+
+type ExitCodeMapper = fn(&(dyn Error + 'static)) -> i32;
+
+static EXIT_CODE_MAPPER: RwLock<Option<ExitCodeMapper>> = RwLock::new(None);
+
+/// Set the function deciding the exit code for errors reported by [`require!`].
+///
+/// When [`require!`] — or any of the other macros exiting on the error of a
+/// [`std::result::Result`], such as [`require_flat!`] or [`must!`] — is given
+/// a [`FatalError`], the program exits with the code returned by `f` called
+/// with the error it holds.  This lets an application define its exit code
+/// policy in one place, and have it apply to every error passed up with the
+/// `?` operator.  An exit code given explicitly, as in `require!((res, code))`
+/// or with [`require_map_code!`], takes precedence over this function.  Other
+/// errors always exit with `1`, as the function is given a `'static` error
+/// which they can’t be known to be.  So do the errors reported by
+/// [`ResultExt::or_die()`].  By default no function is set and the exit code
+/// is `1`.
+///
+/// # Examples
+///
+/// ```
+/// use std::error::Error;
+/// use std::fmt;
+/// use cerm::{require, require_cleanup, require_flat, FatalError};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+/// 	Usage,
+/// 	Config,
+/// }
+///
+/// impl fmt::Display for AppError {
+/// 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// 		f.write_str(match self {
+/// 			Self::Usage => "invalid usage",
+/// 			Self::Config => "invalid configuration",
+/// 		})
+/// 	}
+/// }
+///
+/// impl Error for AppError {}
+///
+/// fn load_config() -> Result<(), FatalError> {
+/// 	Err(AppError::Config)?
+/// }
+///
+/// cerm::set_exit_code_from_error(|e| match e.downcast_ref::<AppError>() {
+/// 	Some(AppError::Usage) => 64,
+/// 	Some(AppError::Config) => 78,
+/// 	None => 1,
+/// });
+///
+/// let res = cerm::catch_fatal(|| require!(load_config()));
+/// assert_eq!(res.unwrap_err().code, 78);
+///
+/// let res = cerm::catch_fatal(|| require!((load_config(), 2)));
+/// assert_eq!(res.unwrap_err().code, 2);
+///
+/// let res = cerm::catch_fatal(|| require!(Err::<(), _>(AppError::Config)));
+/// assert_eq!(res.unwrap_err().code, 1);
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	let inner = Err::<(), FatalError>(AppError::Usage.into());
+/// 	require_flat!(Ok::<_, FatalError>(inner))
+/// });
+/// assert_eq!(res.unwrap_err().code, 64);
+///
+/// let res = cerm::catch_fatal(|| require_cleanup!(load_config(), || ()));
+/// assert_eq!(res.unwrap_err().code, 78);
+/// ```
+///
+/// [`require!`]: crate::require!
+/// [`require_flat!`]: crate::require_flat!
+/// [`must!`]: crate::must!
+/// [`FatalError`]: crate::FatalError
+/// [`ResultExt::or_die()`]: crate::ResultExt::or_die
+/// [`require_map_code!`]: crate::require_map_code!
+pub fn set_exit_code_from_error(f: fn(&(dyn Error + 'static)) -> i32) {
+	*EXIT_CODE_MAPPER.write().unwrap_or_else(PoisonError::into_inner) = Some(f);
+}
+
+pub(crate) fn exit_code_from_error(e: &(dyn Error + 'static)) -> i32 {
+	match *EXIT_CODE_MAPPER.read().unwrap_or_else(PoisonError::into_inner) {
+		Some(f) => f(e),
+		None => 1,
+	}
+}
+
+static TEMPLATES: RwLock<BTreeMap<String, Arc<str>>> = RwLock::new(BTreeMap::new());
+
+/// Register a message template for use with [`err_template!`] and
+/// [`warn_template!`].
+///
+/// The template is a string in which named placeholders such as `{path}` are
+/// replaced by the fields given to the macros.  As with [`format!`], a literal
+/// brace is written by doubling it.  Placeholders with no matching field are
+/// printed as is.  Registering a template with a key that is already taken
+/// replaces the old template.
+///
+/// Keeping the wording of diagnostics in one place makes them easier to keep
+/// consistent, and to translate.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_template;
+///
+/// cerm::register_template("no-input", "cannot read {path}: {reason}");
+/// warn_template!("no-input", path = "in.txt", reason = "permission denied");
+/// assert_eq!(buf.take(), b"prog: cannot read in.txt: permission denied\n");
+/// ```
+///
+/// The fields are formatted without holding on to the templates, so they may
+/// register templates of their own.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use std::fmt;
+/// use cerm::warn_template;
+///
+/// struct Lazy;
+///
+/// impl fmt::Display for Lazy {
+/// 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/// 		cerm::register_template("later", "registered by {who}");
+/// 		f.write_str("a field")
+/// 	}
+/// }
+///
+/// cerm::register_template("now", "formatted {field}");
+/// warn_template!("now", field = Lazy);
+/// warn_template!("later", who = "a field");
+/// assert_eq!(buf.take(), b"prog: formatted a field\nprog: registered by a field\n");
+/// ```
+///
+/// [`err_template!`]: crate::err_template!
+/// [`warn_template!`]: crate::warn_template!
+pub fn register_template(key: impl Into<String>, template: impl Into<String>) {
+	TEMPLATES
+		.write()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(key.into(), template.into().into());
+}
+
+/* Expand the template registered under the given key, or return None if there
+   is no such template */
+fn expand_template(key: &str, fields: &[(&str, &dyn fmt::Display)]) -> Option<String> {
+	/* Formatting the fields runs arbitrary code which may register templates
+	   itself, so the lock mustn’t be held while doing so */
+	let template = TEMPLATES
+		.read()
+		.unwrap_or_else(PoisonError::into_inner)
+		.get(key)?
+		.clone();
+	let mut rest = &*template;
+	let mut buf = String::with_capacity(rest.len());
+	while let Some(i) = rest.find(['{', '}']) {
+		buf.push_str(&rest[..i]);
+		rest = &rest[i..];
+		if rest.starts_with("{{") || rest.starts_with("}}") {
+			buf.push_str(&rest[..1]);
+			rest = &rest[2..];
+			continue;
+		}
+		let field = rest[1..].find('}').and_then(|j| {
+			let name = &rest[1..j + 1];
+			fields.iter().find(|(k, _)| *k == name).map(|(_, v)| (j, v))
+		});
+		match field {
+			Some((j, v)) => {
+				write!(buf, "{v}").expect("formatting a diagnostic failed");
+				rest = &rest[j + 2..];
+			}
+			None => {
+				buf.push_str(&rest[..1]);
+				rest = &rest[1..];
+			}
+		}
+	}
+	buf.push_str(rest);
+	Some(buf)
+}
+
+#[doc(hidden)]
+pub fn __err_template(key: &str, fields: &[(&str, &dyn fmt::Display)]) -> ! {
+	match expand_template(key, fields) {
+		Some(s) => crate::err_fmt(1, format_args!("{s}")),
+		None => crate::err_fmt(1, format_args!("unknown diagnostic template “{key}”")),
+	}
+}
+
+#[doc(hidden)]
+pub fn __warn_template(key: &str, fields: &[(&str, &dyn fmt::Display)]) {
+	match expand_template(key, fields) {
+		Some(s) => crate::warn_fmt(format_args!("{s}")),
+		None => crate::warn_fmt(format_args!("unknown diagnostic template “{key}”")),
+	}
+}
+
+static MAX_MESSAGE_LEN: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Set the maximum length of diagnostic messages.
+///
+/// With `Some(n)`, messages longer than `n` bytes are cut down to at most `n`
+/// bytes and followed by an ellipsis (`…`).  Messages are only ever cut at a
+/// character boundary, so multibyte characters are never split.  This protects
+/// interactive sessions from pathological messages, such as a server
+/// returning a whole web page as an error.  Neither the program name nor the
+/// label set with [`set_fatal_label()`] is counted.  Control characters escaped
+/// as per [`set_sanitize()`] count as printed, and escapes are never split
+/// either.  The default is `None`, which doesn’t limit the length of messages.
+///
+/// Only the printed diagnostics are truncated.  The [`FatalExit`] of a fatal
+/// diagnostic and the [`Diagnostic`]s given to a structured sink set with
+/// [`set_structured_sink()`] always carry the full message, so that a complete
+/// record can be kept while the terminal stays readable.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// cerm::set_max_message_len(Some(5));
+///
+/// cerm::warn!("short");
+/// assert_eq!(buf.take(), b"prog: short\n");
+///
+/// /* “ß” takes up the 5th and 6th bytes */
+/// cerm::warn!("größer");
+/// assert_eq!(String::from_utf8(buf.take()).unwrap(), "prog: grö…\n");
+///
+/// /* Each escape takes up 4 bytes */
+/// cerm::set_sanitize(true);
+/// cerm::set_max_message_len(Some(9));
+/// cerm::warn!("{}", "\x01\x02\x03");
+/// assert_eq!(String::from_utf8(buf.take()).unwrap(), "prog: \\x01\\x02…\n");
+///
+/// cerm::set_fatal_label("fatal:");
+/// cerm::set_max_message_len(Some(5));
+/// let _ = cerm::catch_fatal(|| { cerm::err!("failed"); });
+/// assert_eq!(String::from_utf8(buf.take()).unwrap(), "prog: fatal: faile…\n");
+/// ```
+///
+/// A structured sink receives the full message of a fatal diagnostic, while
+/// the printed one is truncated.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use std::sync::{Arc, Mutex};
+/// use cerm::{Diagnostic, StructuredSink};
+///
+/// #[derive(Clone, Default)]
+/// struct Log(Arc<Mutex<Vec<String>>>);
+///
+/// impl StructuredSink for Log {
+/// 	fn emit(&mut self, diag: &Diagnostic) {
+/// 		self.0.lock().unwrap().push(diag.message.clone());
+/// 	}
+/// }
+///
+/// let log = Log::default();
+/// cerm::set_structured_sink(Box::new(log.clone()));
+/// cerm::set_max_message_len(Some(12));
+///
+/// let res = cerm::catch_fatal(|| { cerm::err!("server said: <html>…</html>"); });
+/// assert_eq!(res.unwrap_err().message, "server said: <html>…</html>");
+/// assert_eq!(String::from_utf8(buf.take()).unwrap(), "prog: server said:…\n");
+/// assert_eq!(*log.0.lock().unwrap(), ["server said: <html>…</html>"]);
+/// ```
+pub fn set_max_message_len(n: Option<usize>) {
+	MAX_MESSAGE_LEN.store(n.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+pub(crate) fn max_message_len() -> usize {
+	MAX_MESSAGE_LEN.load(Ordering::Relaxed)
+}
+
+type ExitCodeFilter = Box<dyn Fn(i32) -> i32 + Send + Sync>;
+
+static EXIT_CODE_FILTER: RwLock<Option<ExitCodeFilter>> = RwLock::new(None);
+
+/// Set a function rewriting the code with which [`err_code!`] exits.
+///
+/// The function is called with the exit code right before the program exits,
+/// and the program exits with the code it returns instead.  It is the very
+/// last step in choosing an exit code, so it also applies to the codes chosen
+/// by [`set_exit_code_from_error()`], and to the code of the [`FatalExit`]
+/// raised when the [`exit_behavior()`] is [`ExitBehavior::Panic`].  This is
+/// useful to remap the codes of a whole program uniformly, for example when
+/// running under a supervisor which interprets some codes specially.  Setting
+/// a new filter replaces the previous one.  By default the code is left as
+/// is.
+///
+/// # Examples
+///
+/// Clamp every exit code to `1`.
+///
+/// ```
+/// use cerm::err_code;
+///
+/// cerm::set_exit_code_filter(Box::new(|code| code.min(1)));
+///
+/// let res = cerm::catch_fatal(|| { err_code!(66, "cannot open input"); });
+/// assert_eq!(res.unwrap_err().code, 1);
+/// ```
+///
+/// [`err_code!`]: crate::err_code!
+pub fn set_exit_code_filter(filter: ExitCodeFilter) {
+	*EXIT_CODE_FILTER.write().unwrap_or_else(PoisonError::into_inner) = Some(filter);
+}
+
+pub(crate) fn filter_exit_code(code: i32) -> i32 {
+	match &*EXIT_CODE_FILTER.read().unwrap_or_else(PoisonError::into_inner) {
+		Some(filter) => filter(code),
+		None => code,
+	}
+}
+
+static FATAL_LABEL: RwLock<String> = RwLock::new(String::new());
+
+/// Set a label to print before the message of every fatal diagnostic.
+///
+/// The label is printed verbatim between the program name and the message of
+/// the diagnostics printed by the [`err!`] family of macros, followed by a
+/// space, so that with a label of `"fatal:"` they take the form
+/// `"progname: fatal: message"`.  Warnings are not affected, which makes the
+/// diagnostics terminating the program easy to tell apart.  The
+/// [`FatalExit`] of a fatal diagnostic carries the message without the label.
+/// By default there is no label.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{err, warn};
+///
+/// cerm::set_fatal_label("fatal:");
+/// warn!("retrying");
+/// let _ = cerm::catch_fatal(|| { err!("giving up"); });
+/// assert_eq!(buf.take(), b"prog: retrying\nprog: fatal: giving up\n");
+/// ```
+///
+/// [`err!`]: crate::err!
+pub fn set_fatal_label(label: impl Into<String>) {
+	*FATAL_LABEL.write().unwrap_or_else(PoisonError::into_inner) = label.into();
+}
+
+pub(crate) fn fatal_label() -> RwLockReadGuard<'static, String> {
+	FATAL_LABEL.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A destination for diagnostics which receives them as [`Diagnostic`]s.
+///
+/// Unlike a sink set with [`set_local_sink()`], which only receives the bytes
+/// of rendered diagnostics, a structured sink is given the level, exit code,
+/// program name, and message of every diagnostic separately.  This lets it
+/// route diagnostics based on their severity, such as when mapping them to
+/// syslog priorities.
+pub trait StructuredSink {
+	/// Receive a diagnostic.
+	fn emit(&mut self, diag: &Diagnostic);
+}
+
+type BoxedStructuredSink = Box<dyn StructuredSink + Send + Sync>;
+
+static STRUCTURED_SINK: Mutex<Option<BoxedStructuredSink>> = Mutex::new(None);
+/* Whether a structured sink was ever set, so that diagnostics don’t all have
+   to take the lock above to find out */
+static HAS_STRUCTURED_SINK: AtomicBool = AtomicBool::new(false);
+
+/// Send every diagnostic to the given structured sink.
+///
+/// The sink receives the diagnostics of all threads, in addition to them being
+/// printed as usual.  Fatal diagnostics are sent before the program exits, and
+/// the messages sent are neither truncated nor escaped.  Setting a new sink
+/// replaces the previous one.
+///
+/// Diagnostics printed by the sink itself are written straight to the standard
+/// error, or the local sink, and are not sent back to the sink.
+///
+/// # Examples
+///
+/// Sort warnings and errors into separate buckets.
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use cerm::{Diagnostic, Level, StructuredSink};
+///
+/// #[derive(Clone, Default)]
+/// struct Buckets {
+/// 	warnings: Arc<Mutex<Vec<String>>>,
+/// 	errors: Arc<Mutex<Vec<String>>>,
+/// }
+///
+/// impl StructuredSink for Buckets {
+/// 	fn emit(&mut self, diag: &Diagnostic) {
+/// 		let bucket = match diag.level {
+/// 			Level::Warning => &self.warnings,
+/// 			Level::Error => &self.errors,
+/// 		};
+/// 		bucket.lock().unwrap().push(diag.message.clone());
+/// 	}
+/// }
+///
+/// let buckets = Buckets::default();
+/// cerm::set_structured_sink(Box::new(buckets.clone()));
+///
+/// cerm::warn!("disk almost full");
+/// let _ = cerm::catch_fatal(|| { cerm::err_code!(74, "disk full"); });
+/// cerm::warn!("retrying");
+///
+/// assert_eq!(*buckets.warnings.lock().unwrap(), ["disk almost full", "retrying"]);
+/// assert_eq!(*buckets.errors.lock().unwrap(), ["disk full"]);
+/// ```
+///
+/// A sink printing a diagnostic of its own doesn’t deadlock.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{Diagnostic, StructuredSink};
+///
+/// struct Noisy;
+///
+/// impl StructuredSink for Noisy {
+/// 	fn emit(&mut self, diag: &Diagnostic) {
+/// 		cerm::warn!("got “{}”", diag.message);
+/// 	}
+/// }
+///
+/// cerm::set_structured_sink(Box::new(Noisy));
+/// cerm::warn!("hello");
+/// assert_eq!(buf.take(), "prog: hello\nprog: got “hello”\n".as_bytes());
+/// ```
+pub fn set_structured_sink(sink: BoxedStructuredSink) {
+	*STRUCTURED_SINK.lock().unwrap_or_else(PoisonError::into_inner) = Some(sink);
+	HAS_STRUCTURED_SINK.store(true, Ordering::Release);
+}
+
+/* Send a diagnostic to the structured sink, only building it if there is a
+   sink */
+pub(crate) fn emit_structured(f: impl FnOnce() -> Diagnostic) {
+	if !HAS_STRUCTURED_SINK.load(Ordering::Acquire) {
+		return;
+	}
+	if let Some(sink) = &mut *STRUCTURED_SINK.lock().unwrap_or_else(PoisonError::into_inner) {
+		sink.emit(&f());
+	}
+}
+
+thread_local! {
+	static CONTEXT: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Push a breadcrumb onto the context stack of the current thread.
+///
+/// Every entry on the context stack is printed between the program name and
+/// the message of the diagnostics of the current thread, each followed by
+/// `": "`, in the order in which they were pushed.  This gives the diagnostics
+/// of deeply nested operations context without threading it through every
+/// function, as in `"mytool: config.toml: [server]: port: invalid value"`.
+/// Prefer the [`context!`] macro, which pops the entry again automatically.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("mytool");
+/// use cerm::warn;
+///
+/// cerm::push_context("config.toml");
+/// cerm::push_context("[server]");
+/// warn!("port: invalid value");
+/// assert_eq!(buf.take(), b"mytool: config.toml: [server]: port: invalid value\n");
+///
+/// assert_eq!(cerm::pop_context().as_deref(), Some("[server]"));
+/// warn!("unknown key “colour”");
+/// assert_eq!(buf.take(), "mytool: config.toml: unknown key “colour”\n".as_bytes());
+/// ```
+///
+/// [`context!`]: crate::context!
+pub fn push_context(s: impl Into<String>) {
+	CONTEXT.with_borrow_mut(|ctx| ctx.push(s.into()));
+}
+
+/// Pop the last breadcrumb pushed onto the context stack of the current
+/// thread.
+///
+/// See [`push_context()`].
+pub fn pop_context() -> Option<String> {
+	CONTEXT.with_borrow_mut(Vec::pop)
+}
+
+/// A guard popping a breadcrumb from the context stack when dropped.
+///
+/// This is returned by the [`context!`] macro.
+///
+/// [`context!`]: crate::context!
+#[must_use = "the breadcrumb is popped as soon as the guard is dropped"]
+pub struct ContextGuard(());
+
+impl ContextGuard {
+	#[doc(hidden)]
+	pub fn __new(s: String) -> Self {
+		push_context(s);
+		Self(())
+	}
+}
+
+impl Drop for ContextGuard {
+	fn drop(&mut self) {
+		pop_context();
+	}
+}
+
+/* Call f with every breadcrumb on the context stack of the current thread */
+pub(crate) fn for_each_context(mut f: impl FnMut(&str) -> fmt::Result) -> fmt::Result {
+	CONTEXT.with_borrow(|ctx| ctx.iter().try_for_each(|s| f(s)))
+}
+
+#[cfg(unix)]
+static STATUS_FD: RwLock<Option<std::os::fd::RawFd>> = RwLock::new(None);
+#[cfg(unix)]
+static STATUS_FD_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Write diagnostics to the file descriptor `fd` instead of the standard
+/// error.
+///
+/// Some supervisors pass their children a dedicated descriptor to report
+/// status on, in the style of the `--status-fd` option of gpg(1).  The
+/// descriptor is not closed by this library; use [`take_status_fd()`] to stop
+/// writing to it.  A sink set with [`set_local_sink()`] still takes
+/// precedence.
+///
+/// If writing to `fd` fails — because it isn’t open, for example — the
+/// diagnostic is written to the standard error instead, and the first such
+/// failure is itself reported there.
+///
+/// # Safety
+///
+/// As long as it is set, `fd` must either be invalid or refer to a file that
+/// the rest of the program doesn’t expect to have exclusive access to.  In
+/// particular, it mustn’t be closed and then reused for an unrelated file.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(unix)] {
+/// # cerm::set_progname("mytool");
+/// use std::io::{self, Read};
+/// use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+///
+/// use cerm::warn;
+///
+/// let (mut r, w) = io::pipe().unwrap();
+/// unsafe { cerm::set_status_fd(w.into_raw_fd()) };
+/// warn!("disk almost full");
+///
+/// /* Close the write end so that reading stops */
+/// let fd = cerm::take_status_fd().unwrap();
+/// drop(unsafe { OwnedFd::from_raw_fd(fd) });
+///
+/// let mut s = String::new();
+/// r.read_to_string(&mut s).unwrap();
+/// assert_eq!(s, "mytool: disk almost full\n");
+/// # }
+/// ```
+#[cfg(unix)]
+pub unsafe fn set_status_fd(fd: std::os::fd::RawFd) {
+	*STATUS_FD.write().unwrap_or_else(PoisonError::into_inner) = Some(fd);
+	STATUS_FD_FAILED.store(false, Ordering::Relaxed);
+}
+
+/// Stop writing diagnostics to the descriptor set with [`set_status_fd()`]
+/// and return it.
+#[cfg(unix)]
+pub fn take_status_fd() -> Option<std::os::fd::RawFd> {
+	STATUS_FD.write().unwrap_or_else(PoisonError::into_inner).take()
+}
+
+/* Write a string to the status descriptor, returning None if there is none */
+#[cfg(unix)]
+pub(crate) fn write_status_fd(s: &str) -> Option<io::Result<()>> {
+	use std::fs::File;
+	use std::mem::ManuallyDrop;
+	use std::os::fd::FromRawFd;
+
+	let fd = (*STATUS_FD.read().unwrap_or_else(PoisonError::into_inner))?;
+	/* File asserts that the descriptor isn’t -1, but any negative descriptor is
+	   equally invalid */
+	if fd < 0 {
+		return Some(Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"invalid file descriptor",
+		)));
+	}
+	/* SAFETY: set_status_fd() requires that writing to the descriptor is fine,
+	   and wrapping the file in ManuallyDrop keeps it from being closed */
+	let f = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+	Some((&*f).write_all(s.as_bytes()))
+}
+
+/* Return whether a status descriptor is set */
+#[cfg(unix)]
+pub(crate) fn has_status_fd() -> bool {
+	STATUS_FD.read().unwrap_or_else(PoisonError::into_inner).is_some()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn has_status_fd() -> bool {
+	false
+}
+
+/* Record that the status descriptor failed, returning whether it is the first
+   time */
+#[cfg(unix)]
+pub(crate) fn status_fd_failed() -> bool {
+	!STATUS_FD_FAILED.swap(true, Ordering::Relaxed)
+}
+
+/// The format diagnostics are written in.
+///
+/// The default is [`OutputFormat::Plain`].  Use [`set_output_format()`] to
+/// change it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OutputFormat {
+	/// Write diagnostics as is.
+	#[default]
+	Plain,
+	/// Write diagnostics as GitHub Actions workflow commands such as
+	/// `::error::message`, so that they show up as annotations.
+	GithubActions,
+	/// Use [`OutputFormat::GithubActions`] if the `GITHUB_ACTIONS`
+	/// environment variable is `true`, as it is in GitHub Actions runners, and
+	/// [`OutputFormat::Plain`] otherwise.
+	Auto,
+}
+
+impl OutputFormat {
+	fn from_u8(n: u8) -> Self {
+		match n {
+			1 => Self::GithubActions,
+			2 => Self::Auto,
+			_ => Self::Plain,
+		}
+	}
+}
+
+static OUTPUT_FORMAT: AtomicU8 = AtomicU8::new(OutputFormat::Plain as u8);
+
+/// Set the format diagnostics are written in.
+///
+/// With [`OutputFormat::GithubActions`], warnings are written as
+/// `::warning::` workflow commands and errors as `::error::` ones.  The rest of
+/// the diagnostic — the program name, the message, and so on — is the
+/// command’s message, with `%`, carriage returns, and newlines percent-encoded
+/// as GitHub requires.  Only what is written to the standard error or a sink
+/// is affected; the [`StructuredSink`] and the messages of [`FatalExit`]s are
+/// not.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use std::env;
+/// use cerm::{err, warn, OutputFormat};
+///
+/// cerm::set_output_format(OutputFormat::GithubActions);
+/// warn!("coverage is at 85%\r\nbelow the threshold");
+/// assert_eq!(buf.take(), b"::warning::prog: coverage is at 85%25%0D%0Abelow the threshold\n");
+///
+/// let res = cerm::catch_fatal(|| { err!("tests failed"); });
+/// assert_eq!(res.unwrap_err().message, "tests failed");
+/// assert_eq!(buf.take(), b"::error::prog: tests failed\n");
+///
+/// cerm::set_output_format(OutputFormat::Auto);
+/// env::set_var("GITHUB_ACTIONS", "false");
+/// warn!("not in a runner");
+/// assert_eq!(buf.take(), b"prog: not in a runner\n");
+/// env::set_var("GITHUB_ACTIONS", "true");
+/// warn!("in a runner");
+/// assert_eq!(buf.take(), b"::warning::prog: in a runner\n");
+/// ```
+pub fn set_output_format(f: OutputFormat) {
+	OUTPUT_FORMAT.store(f as u8, Ordering::Relaxed);
+}
+
+/// Get the format diagnostics are written in.
+///
+/// See [`set_output_format()`].
+pub fn output_format() -> OutputFormat {
+	OutputFormat::from_u8(OUTPUT_FORMAT.load(Ordering::Relaxed))
+}
+
+/* Whether diagnostics should be written as GitHub Actions workflow commands */
+pub(crate) fn github_actions() -> bool {
+	match output_format() {
+		OutputFormat::Plain => false,
+		OutputFormat::GithubActions => true,
+		OutputFormat::Auto => env::var_os("GITHUB_ACTIONS").is_some_and(|v| v == "true"),
+	}
+}
@@ -0,0 +1,1127 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+#[cfg(feature = "full")]
+use std::fmt::Write;
+#[cfg(feature = "full")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "full")]
+use std::panic;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "full")]
+use std::path;
+#[cfg(feature = "full")]
+use std::collections::HashSet;
+use std::io::{self, IsTerminal};
+#[cfg(feature = "full")]
+use std::io::Write as _;
+#[cfg(feature = "full")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "full")]
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::{env, process};
+
+#[cfg(feature = "full")]
+use crate::config::{self, ExitBehavior, FatalExit};
+
+/// Return whether the standard error is a terminal.
+///
+/// This is the check used by this crate to decide whether to emit
+/// terminal-specific output such as the alerts of
+/// [`set_alert_on_error()`](crate::set_alert_on_error) and the hyperlinks of
+/// [`set_hyperlinks()`](crate::set_hyperlinks).  With the `full` feature, the
+/// result is computed once and then cached, so callers assembling their own
+/// output around diagnostics can use it cheaply to stay consistent with the
+/// crate.
+///
+/// # Examples
+///
+/// ```
+/// let bold = if cerm::stderr_is_terminal() { "\x1b[1m" } else { "" };
+/// ```
+pub fn stderr_is_terminal() -> bool {
+	#[cfg(feature = "full")]
+	return *IS_TERMINAL.get_or_init(|| io::stderr().is_terminal());
+	#[cfg(not(feature = "full"))]
+	io::stderr().is_terminal()
+}
+
+#[cfg(feature = "full")]
+static IS_TERMINAL: OnceLock<bool> = OnceLock::new();
+
+/* Pretend that the standard error is a terminal, so that the examples can
+   check the terminal-specific output.  This has no effect once
+   stderr_is_terminal() has been called. */
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __assume_terminal() {
+	let _ = IS_TERMINAL.set(true);
+}
+
+/// Conversion of possibly non-UTF-8 strings for printing in diagnostics.
+///
+/// This trait is implemented for the string types of the standard library,
+/// including [`Path`], [`OsStr`], and byte strings, which may hold data that is
+/// not valid UTF-8 such as file names and the output of other programs.  Any
+/// invalid UTF-8 sequences are replaced by `U+FFFD REPLACEMENT CHARACTER`, as
+/// with [`String::from_utf8_lossy()`], so that such strings are printed as
+/// text instead of with the escapes of their [`Debug`](fmt::Debug) form.  This
+/// is the conversion used by [`warn_path!`] and [`err_path!`].
+///
+/// # Examples
+///
+/// ```
+/// use cerm::ToLossy;
+///
+/// assert_eq!(b"caf\xe9".to_lossy(), "caf\u{fffd}");
+/// assert_eq!(std::path::Path::new("a.txt").to_lossy(), "a.txt");
+/// ```
+///
+/// [`warn_path!`]: crate::warn_path!
+/// [`err_path!`]: crate::err_path!
+pub trait ToLossy {
+	/// Convert to a string, replacing invalid UTF-8 sequences.
+	fn to_lossy(&self) -> Cow<'_, str>;
+}
+
+impl ToLossy for str {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		Cow::Borrowed(self)
+	}
+}
+
+impl ToLossy for String {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		Cow::Borrowed(self)
+	}
+}
+
+impl ToLossy for [u8] {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		String::from_utf8_lossy(self)
+	}
+}
+
+impl<const N: usize> ToLossy for [u8; N] {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		String::from_utf8_lossy(self)
+	}
+}
+
+impl ToLossy for Vec<u8> {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		String::from_utf8_lossy(self)
+	}
+}
+
+impl ToLossy for OsStr {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		self.to_string_lossy()
+	}
+}
+
+impl ToLossy for OsString {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		self.to_string_lossy()
+	}
+}
+
+impl ToLossy for Path {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		self.to_string_lossy()
+	}
+}
+
+impl ToLossy for PathBuf {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		self.to_string_lossy()
+	}
+}
+
+impl<T: ToLossy + ?Sized> ToLossy for &T {
+	fn to_lossy(&self) -> Cow<'_, str> {
+		(**self).to_lossy()
+	}
+}
+
+#[doc(hidden)]
+pub struct __FileLink<'a>(pub &'a Path);
+
+impl fmt::Display for __FileLink<'_> {
+	#[cfg(not(feature = "full"))]
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0.display())
+	}
+
+	#[cfg(feature = "full")]
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if !config::hyperlinks() || !stderr_is_terminal() {
+			return write!(f, "{}", self.0.display());
+		}
+		let Ok(abs) = path::absolute(self.0) else {
+			return write!(f, "{}", self.0.display());
+		};
+		let mut link = String::from("\x1b]8;;file://");
+		for b in abs.to_string_lossy().bytes() {
+			match b {
+				b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+					link.push(b as char)
+				}
+				_ => write!(link, "%{b:02X}").unwrap(),
+			}
+		}
+		link.push_str("\x1b\\");
+		write_markup(f, &link)?;
+		write!(f, "{}", self.0.display())?;
+		write_markup(f, "\x1b]8;;\x1b\\")
+	}
+}
+
+#[cfg(feature = "full")]
+thread_local! {
+	/* Whether the escapes of __FileLink are being written */
+	static MARKUP: Cell<bool> = const { Cell::new(false) };
+}
+
+/* Write terminal escapes which are part of a diagnostic, rather than of its
+   message, so that they are not sanitized */
+#[cfg(feature = "full")]
+fn write_markup(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+	MARKUP.set(true);
+	let res = f.write_str(s);
+	MARKUP.set(false);
+	res
+}
+
+/* A message followed by “: ” and something else, such as an error.  To avoid
+   doubling the separator, a colon already ending the message is dropped. */
+#[doc(hidden)]
+pub struct __Context<'a>(pub fmt::Arguments<'a>);
+
+impl fmt::Display for __Context<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let s = match self.0.as_str() {
+			Some(s) => s.into(),
+			None => self.0.to_string(),
+		};
+		let s = s
+			.strip_suffix(": ")
+			.or_else(|| s.strip_suffix(':'))
+			.unwrap_or(&s);
+		f.write_str(s)
+	}
+}
+
+/* Multi-line details printed beneath a diagnostic.  Every line is printed on
+   a new line and indented by two spaces. */
+#[doc(hidden)]
+pub struct __Detail<'a>(pub fmt::Arguments<'a>);
+
+impl fmt::Display for __Detail<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let s = match self.0.as_str() {
+			Some(s) => s.into(),
+			None => self.0.to_string(),
+		};
+		for line in s.lines() {
+			match line {
+				"" => f.write_str("\n")?,
+				_ => write!(f, "\n  {line}")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+fn progname() -> String {
+	env::args().next().unwrap_or("Error".into())
+}
+
+/* The program name printed before every diagnostic message */
+struct Progname;
+
+impl fmt::Display for Progname {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		#[cfg(feature = "full")]
+		{
+			if let Some(r) = config::with_thread_progname(|name| name.map(|s| f.write_str(s))) {
+				return r;
+			}
+			if let Some(name) = &*config::progname() {
+				return f.write_str(name);
+			}
+			if let Some(r) = config::with_env_progname(|name| name.map(|s| f.write_str(s))) {
+				return r;
+			}
+		}
+		f.write_str(&progname())
+	}
+}
+
+/* The prefix printed before every diagnostic message */
+struct Prefix;
+
+impl fmt::Display for Prefix {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		#[cfg(feature = "full")]
+		{
+			let label = config::label_prefix();
+			if !label.is_empty() {
+				write!(f, "{label} ")?;
+			}
+		}
+		write!(f, "{Progname}: ")?;
+		#[cfg(feature = "full")]
+		config::for_each_context(|s| write!(f, "{s}: "))?;
+		Ok(())
+	}
+}
+
+/* An error without a message is almost certainly a mistake — exit_quiet!() is
+   meant for exiting silently — so point it out in debug builds */
+fn warn_empty() {
+	warn(format_args!("fatal diagnostic has an empty message"));
+}
+
+/* Without the “full” feature there is nothing to configure, so diagnostics are
+   printed directly */
+
+#[cfg(not(feature = "full"))]
+fn warn(args: fmt::Arguments) {
+	eprintln!("{Prefix}{args}");
+}
+
+#[cfg(not(feature = "full"))]
+fn out(args: fmt::Arguments) {
+	println!("{Prefix}{args}");
+}
+
+#[cfg(not(feature = "full"))]
+fn err(code: i32, args: fmt::Arguments) -> ! {
+	report_err(code, args);
+	process::exit(code);
+}
+
+#[cfg(not(feature = "full"))]
+pub(crate) fn report_err(_code: i32, args: fmt::Arguments) {
+	if cfg!(debug_assertions) {
+		/* Format the message only once, for both the check and the diagnostic */
+		let s = fmt::format(args);
+		if s.is_empty() {
+			warn_empty();
+		}
+		warn(format_args!("{s}"));
+	} else {
+		warn(args);
+	}
+}
+
+#[cfg(not(feature = "full"))]
+#[doc(hidden)]
+pub fn __exit(code: i32) -> ! {
+	process::exit(code);
+}
+
+#[cfg(not(feature = "full"))]
+#[doc(hidden)]
+pub fn __warn_raw(args: fmt::Arguments) {
+	eprintln!("{args}");
+}
+
+#[cfg(not(feature = "full"))]
+#[doc(hidden)]
+pub fn __err_raw(code: i32, args: fmt::Arguments) -> ! {
+	eprintln!("{args}");
+	process::exit(code);
+}
+
+/* Render a complete diagnostic, including the prefix and line terminator */
+#[cfg(feature = "full")]
+fn render(args: fmt::Arguments) -> String {
+	render_after(Prefix.to_string(), args)
+}
+
+/* Render a diagnostic after the given prefix */
+#[cfg(feature = "full")]
+fn render_after(buf: String, args: fmt::Arguments) -> String {
+	render_body(buf, args).0
+}
+
+/* Render a fatal diagnostic after the given prefix */
+#[cfg(feature = "full")]
+fn render_fatal(buf: String, args: fmt::Arguments) -> String {
+	let (s, empty) = render_body(buf, args);
+	if cfg!(debug_assertions) && empty {
+		warn_empty();
+	}
+	s
+}
+
+/* Render a diagnostic after the given prefix, and return whether its message
+   was empty */
+#[cfg(feature = "full")]
+fn render_body(mut buf: String, args: fmt::Arguments) -> (String, bool) {
+	let mut w = Body {
+		buf: &mut buf,
+		sanitize: config::sanitize().unwrap_or_else(stderr_is_terminal),
+		left: config::max_message_len(),
+		truncated: false,
+		empty: true,
+	};
+	/* Messages without any arguments don’t need to go through the formatting
+	   machinery */
+	match args.as_str() {
+		Some(s) => w.write_str(s),
+		None => fmt::write(&mut w, args),
+	}
+	.expect("formatting a diagnostic failed");
+	let empty = w.empty;
+	if w.truncated {
+		buf.push('…');
+	}
+	buf.push_str(config::record_terminator().as_str());
+	(buf, empty)
+}
+
+/* The message of a diagnostic being rendered.  When sanitizing, all control
+   characters other than newlines and tabs are escaped as they are written,
+   save for those written with write_markup().  Once the maximum message length
+   is reached the rest of the message is dropped, escapes included, but the
+   markup is kept and doesn’t count towards the length. */
+#[cfg(feature = "full")]
+struct Body<'a> {
+	buf: &'a mut String,
+	sanitize: bool,
+	left: usize,
+	truncated: bool,
+	empty: bool,
+}
+
+#[cfg(feature = "full")]
+impl fmt::Write for Body<'_> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		if MARKUP.get() {
+			self.buf.push_str(s);
+			return Ok(());
+		}
+		self.empty &= s.is_empty();
+		let is_bad = |c: char| self.sanitize && c.is_control() && c != '\n' && c != '\t';
+		if s.len() <= self.left && !s.contains(is_bad) {
+			self.buf.push_str(s);
+			self.left -= s.len();
+			return Ok(());
+		}
+		for c in s.chars() {
+			/* Control characters are all below U+0100, so they are escaped as
+			   \xNN */
+			let bad = is_bad(c);
+			let n = if bad { 4 } else { c.len_utf8() };
+			if n > self.left {
+				self.left = 0;
+				self.truncated = true;
+				break;
+			}
+			self.left -= n;
+			if bad {
+				write!(self.buf, "\\x{:02x}", c as u32)?;
+			} else {
+				self.buf.push(c);
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "full")]
+thread_local! {
+	/* The diagnostics held back by with_grouped_output!() */
+	static GROUP: RefCell<Option<String>> = const { RefCell::new(None) };
+	/* Whether the current thread is printing a diagnostic */
+	static EMITTING: Cell<bool> = const { Cell::new(false) };
+	/* Whether the current thread is handling a fatal diagnostic */
+	static FATAL: Cell<bool> = const { Cell::new(false) };
+}
+
+/* A guard marking the current thread as printing a diagnostic.  A diagnostic
+   printed while the thread already is — from a signal handler or a sink — must
+   not take the locks the outer diagnostic may be holding, so it is written out
+   with write_raw() instead. */
+#[cfg(feature = "full")]
+struct Emitting;
+
+#[cfg(feature = "full")]
+impl Emitting {
+	fn enter() -> Option<Self> {
+		(!EMITTING.replace(true)).then_some(Self)
+	}
+}
+
+#[cfg(feature = "full")]
+impl Drop for Emitting {
+	fn drop(&mut self) {
+		EMITTING.set(false);
+	}
+}
+
+/// The exit code used when a fatal diagnostic is raised while another is being
+/// handled.
+///
+/// If printing a fatal diagnostic or exiting — in a sink, a panic hook, or the
+/// [`set_async_flush_hook()`] hook — itself leads to a fatal diagnostic, the
+/// second one is printed straight to the standard error and the process exits
+/// immediately with this code, which is `EX_SOFTWARE` from `<sysexits.h>`.  No
+/// hooks are run and the exit code filters and [`exit_behavior()`] are ignored,
+/// as they may be what failed in the first place.
+///
+/// # Examples
+///
+/// ```
+/// use std::process;
+///
+/// match process::Command::new("mytool").status() {
+/// 	Ok(s) if s.code() == Some(cerm::REENTRANT_EXIT_CODE) => {
+/// 		cerm::warn!("mytool failed to report an error");
+/// 	}
+/// 	_ => (),
+/// }
+/// ```
+///
+/// [`set_async_flush_hook()`]: crate::set_async_flush_hook
+/// [`exit_behavior()`]: crate::exit_behavior
+#[cfg(feature = "full")]
+pub const REENTRANT_EXIT_CODE: i32 = 70;
+
+/* A guard marking the current thread as handling a fatal diagnostic, which is
+   only ever dropped when unwinding with ExitBehavior::Panic */
+#[cfg(feature = "full")]
+struct Fatal;
+
+#[cfg(feature = "full")]
+impl Fatal {
+	fn enter() -> Self {
+		if FATAL.replace(true) {
+			process::exit(REENTRANT_EXIT_CODE);
+		}
+		Self
+	}
+}
+
+#[cfg(feature = "full")]
+impl Drop for Fatal {
+	fn drop(&mut self) {
+		FATAL.set(false);
+	}
+}
+
+/* Write a string to the local sink if it is free, or to the standard error,
+   bypassing grouping, deduplication, and the structured sink */
+#[cfg(feature = "full")]
+fn write_raw(s: &str) {
+	match config::write_local_sink(s) {
+		Some(Ok(())) => (),
+		_ => eprint!("{s}"),
+	}
+}
+
+#[doc(hidden)]
+#[cfg(feature = "full")]
+pub struct __OutputGroup(bool);
+
+#[cfg(feature = "full")]
+impl __OutputGroup {
+	pub fn begin() -> Self {
+		/* Only the outermost group writes anything out */
+		Self(GROUP.with_borrow_mut(|g| g.is_none() && g.replace(String::new()).is_none()))
+	}
+}
+
+#[cfg(feature = "full")]
+impl Drop for __OutputGroup {
+	fn drop(&mut self) {
+		if self.0 {
+			end_group();
+		}
+	}
+}
+
+/* Write out the diagnostics held back in the current group, if any */
+#[cfg(feature = "full")]
+fn end_group() {
+	if let Some(s) = GROUP.with_borrow_mut(Option::take) {
+		if !s.is_empty() {
+			write_stderr(&s);
+		}
+	}
+}
+
+/* Write a string to the standard error as write_out() does, unless it is held
+   back by with_grouped_output!() */
+#[cfg(feature = "full")]
+fn write_stderr(s: &str) {
+	let grouped = GROUP.with_borrow_mut(|g| match g {
+		Some(g) => {
+			g.push_str(s);
+			true
+		}
+		None => false,
+	});
+	if !grouped {
+		write_out(false, s);
+	}
+}
+
+/* Write a string to the standard output or error, to the status descriptor if
+   it is for the standard error, or to the local sink if there is one */
+#[cfg(feature = "full")]
+fn write_out(stdout: bool, s: &str) {
+	let print = |s: &str| write_stream(stdout || config::all_to_stdout(), s);
+	#[cfg(unix)]
+	let print = |s: &str| match (!stdout).then(|| config::write_status_fd(s)).flatten() {
+		Some(Ok(())) => (),
+		Some(Err(e)) => {
+			if config::status_fd_failed() {
+				print(&format!(
+					"{}writing to status file descriptor failed: {e}{}",
+					Prefix,
+					config::line_ending().as_str(),
+				));
+			}
+			print(s);
+		}
+		None => print(s),
+	};
+	match config::write_local_sink(s) {
+		Some(Ok(())) => (),
+		Some(Err(e)) => {
+			if config::local_sink_failed() {
+				print(&format!(
+					"{}writing to diagnostic sink failed: {e}{}",
+					Prefix,
+					config::line_ending().as_str(),
+				));
+			}
+			print(s);
+		}
+		None => print(s),
+	}
+}
+
+/* Write a string to the standard output or error.  As when the standard error
+   is closed, nothing happens if the reading end of a pipe was closed; any other
+   error panics, as it does with eprint!(). */
+#[cfg(feature = "full")]
+fn write_stream(stdout: bool, s: &str) {
+	let res = if stdout {
+		let mut out = io::stdout().lock();
+		out.write_all(s.as_bytes()).and_then(|()| out.flush())
+	} else {
+		io::stderr().lock().write_all(s.as_bytes())
+	};
+	if let Err(e) = res {
+		if e.kind() != io::ErrorKind::BrokenPipe {
+			let name = if stdout { "stdout" } else { "stderr" };
+			panic!("failed printing to {name}: {e}");
+		}
+	}
+}
+
+#[cfg(feature = "full")]
+thread_local! {
+	/* The buffer warnings are rendered into.  It is kept around so that once
+	   it has grown large enough, printing a warning doesn’t allocate.  A
+	   warning printed while rendering another one finds it taken, and gets a
+	   buffer of its own. */
+	static WARN_BUF: Cell<String> = const { Cell::new(String::new()) };
+}
+
+/* Warnings longer than this don’t keep their buffer around */
+#[cfg(feature = "full")]
+const WARN_BUF_MAX: usize = 4096;
+
+#[cfg(feature = "full")]
+fn warn(args: fmt::Arguments) {
+	let mut buf = WARN_BUF.take();
+	buf.clear();
+	write!(buf, "{Prefix}").expect("formatting a diagnostic failed");
+	let s = render_after(buf, args);
+	warn_rendered(&s, args);
+	if s.capacity() <= WARN_BUF_MAX {
+		WARN_BUF.set(s);
+	}
+}
+
+/* Turn a rendered diagnostic into a GitHub Actions workflow command, if they
+   are enabled */
+#[cfg(feature = "full")]
+fn annotate(level: Level, s: &str) -> Cow<'_, str> {
+	if !config::github_actions() {
+		return Cow::Borrowed(s);
+	}
+	let term = config::record_terminator().as_str();
+	let msg = s.strip_suffix(term).unwrap_or(s);
+	let mut buf = String::from(match level {
+		Level::Warning => "::warning::",
+		Level::Error => "::error::",
+	});
+	for c in msg.chars() {
+		match c {
+			'%' => buf.push_str("%25"),
+			'\r' => buf.push_str("%0D"),
+			'\n' => buf.push_str("%0A"),
+			c => buf.push(c),
+		}
+	}
+	buf.push_str(term);
+	Cow::Owned(buf)
+}
+
+#[cfg(feature = "full")]
+fn warn_rendered(s: &str, args: fmt::Arguments) {
+	let s = annotate(Level::Warning, s);
+	let Some(_emitting) = Emitting::enter() else {
+		return write_raw(&s);
+	};
+	if !config::is_repeat(&s) {
+		write_stderr(&s);
+		config::emit_structured(|| Diagnostic::new(Level::Warning, None, args));
+		#[cfg(all(feature = "journald", target_os = "linux"))]
+		crate::journald::send(Level::Warning, Progname, args);
+	}
+}
+
+#[cfg(feature = "full")]
+fn out(args: fmt::Arguments) {
+	let s = render(args);
+	let Some(_emitting) = Emitting::enter() else {
+		return write_stream(true, &s);
+	};
+	if !config::is_repeat(&s) {
+		write_out(true, &s);
+		config::emit_structured(|| Diagnostic::new(Level::Warning, None, args));
+	}
+}
+
+#[cfg(feature = "full")]
+fn err(code: i32, args: fmt::Arguments) -> ! {
+	err_rendered(code, render_err(args), args);
+}
+
+/* Print an error like err() does, but without exiting */
+#[cfg(feature = "full")]
+pub(crate) fn report_err(code: i32, args: fmt::Arguments) {
+	write_err(code, &annotate(Level::Error, &render_err(args)), args);
+}
+
+#[cfg(feature = "full")]
+fn render_err(args: fmt::Arguments) -> String {
+	let mut buf = Prefix.to_string();
+	/* The label is part of the prefix, so that it is neither sanitized nor
+	   counted towards the maximum message length */
+	let label = config::fatal_label();
+	if !label.is_empty() {
+		buf.push_str(&label);
+		buf.push(' ');
+	}
+	drop(label);
+	render_fatal(buf, args)
+}
+
+#[cfg(feature = "full")]
+fn err_rendered(code: i32, s: String, args: fmt::Arguments) -> ! {
+	let s = annotate(Level::Error, &s);
+	if FATAL.get() {
+		write_raw(&s);
+	}
+	let _fatal = Fatal::enter();
+	write_err(code, &s, args);
+	exit(code, args);
+}
+
+/* Return whether diagnostics are written to the standard error, and it is a
+   terminal */
+#[cfg(feature = "full")]
+fn writing_to_terminal() -> bool {
+	stderr_is_terminal()
+		&& !config::all_to_stdout()
+		&& !config::has_local_sink()
+		&& !config::has_status_fd()
+}
+
+#[cfg(feature = "full")]
+fn write_err(code: i32, s: &str, args: fmt::Arguments) {
+	match Emitting::enter() {
+		Some(_emitting) => {
+			/* The alert goes out in the same write as the diagnostic, so that
+			   nothing is printed in between */
+			let alert = config::alert_on_error();
+			if alert != config::AlertStyle::None && writing_to_terminal() {
+				write_stderr(&format!("{}{s}", alert.as_str()));
+			} else {
+				write_stderr(s);
+			}
+			config::emit_structured(|| Diagnostic::new(Level::Error, Some(code), args));
+			#[cfg(all(feature = "journald", target_os = "linux"))]
+			crate::journald::send(Level::Error, Progname, args);
+		}
+		None => write_raw(s),
+	}
+}
+
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __warn_raw(args: fmt::Arguments) {
+	warn_rendered(&render_after(String::new(), args), args);
+}
+
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __err_raw(code: i32, args: fmt::Arguments) -> ! {
+	err_rendered(code, render_fatal(String::new(), args), args);
+}
+
+/* Exit without printing a diagnostic */
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __exit(code: i32) -> ! {
+	let _fatal = Fatal::enter();
+	exit(code, format_args!(""));
+}
+
+#[cfg(feature = "full")]
+fn exit(code: i32, args: fmt::Arguments) -> ! {
+	let code = config::filter_exit_code(code);
+	/* Pending output must be written out even if the panic is caught */
+	end_group();
+	config::run_flush_hook();
+	/* The diagnostic was already printed, so skip the panic hook when we know
+	   that catch_fatal() is going to catch the panic. */
+	if config::catching() {
+		panic::resume_unwind(Box::new(FatalExit {
+			code,
+			message: args.to_string(),
+		}));
+	}
+	if config::exit_behavior() == ExitBehavior::Panic {
+		panic::panic_any(FatalExit {
+			code,
+			message: args.to_string(),
+		});
+	}
+	if config::abort_on_error() {
+		process::abort();
+	}
+	process::exit(code);
+}
+
+/// Print a warning built from preformatted arguments.
+///
+/// This is the function behind [`warn!`], which is nothing more than a call to
+/// this function with [`format_args!`].  It is useful when wrapping this crate
+/// in your own functions or macros, as [`fmt::Arguments`] can be passed along
+/// without first being formatted into a [`String`].
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// use cerm::warn;
+///
+/// let path = "/etc/foo.conf";
+/// warn!("{path}: file is empty");
+/// cerm::warn_fmt(format_args!("{path}: file is empty"));
+///
+/// let out = String::from_utf8(buf.take()).unwrap();
+/// let (a, b) = out.split_at(out.len() / 2);
+/// assert_eq!(a, b);
+/// # }
+/// ```
+///
+/// [`warn!`]: crate::warn!
+pub fn warn_fmt(args: fmt::Arguments) {
+	warn(args);
+}
+
+/// Print an error built from preformatted arguments and exit.
+///
+/// This is the function behind [`err_code!`] and [`err!`].  It behaves exactly
+/// like [`err_code!`] with the exit code `code`.
+///
+/// # Panics
+///
+/// Calls [`eprint!`] and [`std::env::args`] which may both panic if they fail.
+///
+/// # Examples
+///
+/// ```no_run
+/// fn die(code: i32, args: std::fmt::Arguments) -> ! {
+/// 	cerm::err_fmt(code, format_args!("fatal: {args}"));
+/// }
+///
+/// die(2, format_args!("{} is not a directory", "/tmp/foo"));
+/// ```
+///
+/// [`err_code!`]: crate::err_code!
+/// [`err!`]: crate::err!
+pub fn err_fmt(code: i32, args: fmt::Arguments) -> ! {
+	err(code, args);
+}
+
+/// Print preformatted arguments to the standard output.
+///
+/// This is the function behind [`outln!`].
+///
+/// # Examples
+///
+/// ```
+/// cerm::outln_fmt(format_args!("{} files removed", 3));
+/// ```
+///
+/// [`outln!`]: crate::outln!
+pub fn outln_fmt(args: fmt::Arguments) {
+	out(args);
+}
+
+/// The severity of a diagnostic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Level {
+	/// A diagnostic printed with [`warn!`], after which the program carries on.
+	///
+	/// [`warn!`]: crate::warn!
+	#[default]
+	Warning,
+	/// A diagnostic printed with [`err!`], after which the program exits.
+	///
+	/// [`err!`]: crate::err!
+	Error,
+}
+
+/// A diagnostic, with its parts kept apart.
+///
+/// This is what a [`StructuredSink`](crate::StructuredSink) is given for
+/// every diagnostic, so that it can act on the level or exit code of a
+/// diagnostic without having to parse it.  A diagnostic is displayed in the
+/// form `"progname: message"`, without a line terminator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+	/// The severity of the diagnostic.
+	pub level: Level,
+	/// The exit code of a fatal diagnostic, or `None` for a warning.
+	pub code: Option<i32>,
+	/// The name of the program.
+	pub progname: String,
+	/// The message, without the program name or line terminator.
+	pub message: String,
+}
+
+impl Diagnostic {
+	/// Parse a diagnostic from a line of output in the form
+	/// `"progname: message"`.
+	///
+	/// This allows relaying the diagnostics of another program, such as a
+	/// child process, while keeping its program name.  The line is split at
+	/// the first `": "`, and a trailing line terminator is removed.  As the
+	/// level and exit code aren’t part of the output, the diagnostic is a
+	/// [`Level::Warning`] with no exit code.
+	///
+	/// This is only a heuristic: a program name containing `": "` can’t be
+	/// told apart from the start of the message.  `None` is returned if the
+	/// line contains no `": "` at all.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use cerm::{Diagnostic, Level};
+	///
+	/// let diag = Diagnostic::parse("cc: unused variable “x”\n").unwrap();
+	/// assert_eq!(diag, Diagnostic {
+	/// 	level: Level::Warning,
+	/// 	code: None,
+	/// 	progname: "cc".into(),
+	/// 	message: "unused variable “x”".into(),
+	/// });
+	///
+	/// assert_eq!(Diagnostic::parse("no separator here"), None);
+	/// ```
+	pub fn parse(line: &str) -> Option<Self> {
+		let line = line.strip_suffix('\n').unwrap_or(line);
+		let line = line.strip_suffix('\r').unwrap_or(line);
+		let (progname, message) = line.split_once(": ")?;
+		Some(Self {
+			level: Level::Warning,
+			code: None,
+			progname: progname.into(),
+			message: message.into(),
+		})
+	}
+
+	fn new(level: Level, code: Option<i32>, args: fmt::Arguments) -> Self {
+		Self {
+			level,
+			code,
+			progname: Progname.to_string(),
+			message: args.to_string(),
+		}
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.progname, self.message)
+	}
+}
+
+/// Record a warning built from preformatted arguments instead of printing it.
+///
+/// The warning is pushed onto `diags` as a [`Diagnostic`] with the current
+/// program name, and nothing is printed.  This lets library code collect its
+/// diagnostics without any global state and leave it up to the caller to
+/// decide how to present them, for example by printing them with
+/// [`warn_raw!`].  See also [`collect_err()`].
+///
+/// # Examples
+///
+/// ```
+/// use cerm::{Diagnostic, Level};
+///
+/// fn parse(input: &str, diags: &mut Vec<Diagnostic>) -> Vec<i32> {
+/// 	input
+/// 		.split(',')
+/// 		.filter_map(|s| match s.trim().parse() {
+/// 			Ok(n) => Some(n),
+/// 			Err(e) => {
+/// 				cerm::collect_warn(diags, format_args!("“{s}”: {e}"));
+/// 				None
+/// 			}
+/// 		})
+/// 		.collect()
+/// }
+///
+/// let mut diags = Vec::new();
+/// assert_eq!(parse("1, x, 3, y", &mut diags), [1, 3]);
+/// assert_eq!(diags.len(), 2);
+/// assert!(diags.iter().all(|d| d.level == Level::Warning));
+/// assert_eq!(diags[0].message, "“ x”: invalid digit found in string");
+/// ```
+///
+/// [`warn_raw!`]: crate::warn_raw!
+pub fn collect_warn(diags: &mut Vec<Diagnostic>, args: fmt::Arguments) {
+	diags.push(Diagnostic::new(Level::Warning, None, args));
+}
+
+/// Record an error built from preformatted arguments instead of printing it
+/// and exiting.
+///
+/// This is the same thing as [`collect_warn()`], but the diagnostic is a
+/// [`Level::Error`] with the given exit code.  The program does not exit.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::Level;
+///
+/// let mut diags = Vec::new();
+/// cerm::collect_err(&mut diags, 65, format_args!("no input given"));
+/// assert_eq!(diags[0].level, Level::Error);
+/// assert_eq!(diags[0].code, Some(65));
+/// ```
+pub fn collect_err(diags: &mut Vec<Diagnostic>, code: i32, args: fmt::Arguments) {
+	diags.push(Diagnostic::new(Level::Error, Some(code), args));
+}
+
+/// Print a diagnostic of the given level built from preformatted arguments.
+///
+/// This is the function behind [`diag!`] and [`diag_code!`].  With
+/// [`Level::Error`] it behaves like [`err_fmt()`] and exits with the given
+/// code, while with [`Level::Warning`] it behaves like [`warn_fmt()`] and the
+/// code is ignored.
+///
+/// # Panics
+///
+/// Calls [`eprint!`] and [`std::env::args`] which may both panic if they fail.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::Level;
+///
+/// cerm::diag_fmt(Level::Warning, 1, format_args!("this one returns"));
+/// ```
+///
+/// [`diag!`]: crate::diag!
+/// [`diag_code!`]: crate::diag_code!
+pub fn diag_fmt(level: Level, code: i32, args: fmt::Arguments) {
+	match level {
+		Level::Warning => warn(args),
+		Level::Error => err(code, args),
+	}
+}
+
+/// Print an already rendered diagnostic of the given level.
+///
+/// This is a fully dynamic counterpart to [`diag_fmt()`] for callers that
+/// can’t use macros or [`fmt::Arguments`], such as bindings for C or scripting
+/// languages.  The message is treated exactly as if it had been formatted by
+/// [`warn!`] or [`err_code!`], with the program name and everything else
+/// configured applied to it.  With [`Level::Error`] the program exits with
+/// `code`, or with 1 if `code` is [`None`]; with [`Level::Warning`] the code is
+/// ignored.
+///
+/// # Panics
+///
+/// Calls [`eprint!`] and [`std::env::args`] which may both panic if they fail.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::Level;
+///
+/// cerm::emit_raw(Level::Warning, None, "plugin loaded twice");
+/// assert_eq!(buf.take(), b"prog: plugin loaded twice\n");
+///
+/// let res = cerm::catch_fatal(|| cerm::emit_raw(Level::Error, Some(3), "plugin crashed"));
+/// assert_eq!(res.unwrap_err().code, 3);
+/// assert_eq!(buf.take(), b"prog: plugin crashed\n");
+///
+/// let res = cerm::catch_fatal(|| cerm::emit_raw(Level::Error, None, "plugin crashed"));
+/// assert_eq!(res.unwrap_err().code, 1);
+/// # }
+/// ```
+///
+/// [`warn!`]: crate::warn!
+/// [`err_code!`]: crate::err_code!
+pub fn emit_raw(level: Level, code: Option<i32>, message: &str) {
+	diag_fmt(level, code.unwrap_or(1), format_args!("{message}"));
+}
+
+#[cfg(feature = "full")]
+static DEPRECATED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __warn_deprecated(key: &str, args: fmt::Arguments) {
+	/* The lock mustn’t be held while warning, as a sink may itself warn about
+	   something deprecated */
+	let first = DEPRECATED
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.get_or_insert_with(HashSet::new)
+		.insert(key.to_owned());
+	if first {
+		warn(format_args!("deprecated: {args}"));
+	}
+}
+
+#[cfg(feature = "full")]
+static WARNED_ONCE: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "full")]
+#[doc(hidden)]
+pub fn __warn_global_once(args: fmt::Arguments) {
+	if !WARNED_ONCE.swap(true, Ordering::Relaxed) {
+		warn(args);
+	}
+}
@@ -0,0 +1,117 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::{PoisonError, RwLock};
+
+use crate::Level;
+
+static JOURNAL: RwLock<Option<UnixDatagram>> = RwLock::new(None);
+
+/// Mirror every diagnostic to the systemd journal.
+///
+/// Once enabled, every diagnostic is also sent to the journal using its native
+/// protocol, in addition to being printed as usual.  Each journal entry
+/// carries the message, the base name of the program name as its
+/// `SYSLOG_IDENTIFIER`, and a `PRIORITY` of `3` (error) for fatal diagnostics
+/// or `4` (warning) for warnings.  Sending is best-effort: entries which can’t be sent, such as
+/// those too large for a single datagram, are silently dropped.
+///
+/// This function is only available on Linux with the `journald` feature.
+///
+/// # Errors
+///
+/// Returns an error if the journal socket can’t be connected to, such as when
+/// the program isn’t running under systemd.
+///
+/// # Examples
+///
+/// ```
+/// if let Err(e) = cerm::enable_journald() {
+/// 	cerm::warn!("cannot log to the journal: {e}");
+/// }
+/// cerm::warn!("this is also sent to the journal, if possible");
+/// ```
+///
+/// Check the entries sent, with a socket standing in for the journal.
+///
+/// ```
+/// use std::os::unix::net::UnixDatagram;
+///
+/// # let _buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// let dir = std::env::temp_dir().join(format!("cerm-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let path = dir.join("socket");
+/// # let _ = std::fs::remove_file(&path);
+/// let journal = UnixDatagram::bind(&path).unwrap();
+/// cerm::__enable_journald_at(&path).unwrap();
+///
+/// let mut buf = [0; 1024];
+/// cerm::warn!("disk almost full");
+/// let n = journal.recv(&mut buf).unwrap();
+/// assert_eq!(
+/// 	&buf[..n],
+/// 	b"PRIORITY=4\nSYSLOG_IDENTIFIER=prog\nMESSAGE=disk almost full\n",
+/// );
+///
+/// cerm::set_progname("/usr/bin/prog");
+/// let _ = cerm::catch_fatal(|| cerm::err!("disk full"));
+/// let n = journal.recv(&mut buf).unwrap();
+/// assert_eq!(
+/// 	&buf[..n],
+/// 	b"PRIORITY=3\nSYSLOG_IDENTIFIER=prog\nMESSAGE=disk full\n",
+/// );
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn enable_journald() -> io::Result<()> {
+	__enable_journald_at("/run/systemd/journal/socket")
+}
+
+/* Connect to the journal at the given path, so that the examples can stand
+   in for it */
+#[doc(hidden)]
+pub fn __enable_journald_at(path: impl AsRef<Path>) -> io::Result<()> {
+	let sock = UnixDatagram::unbound()?;
+	sock.connect(path)?;
+	*JOURNAL.write().unwrap_or_else(PoisonError::into_inner) = Some(sock);
+	Ok(())
+}
+
+/* Send a diagnostic to the journal, if enabled */
+pub(crate) fn send(level: Level, progname: impl fmt::Display, args: fmt::Arguments) {
+	let journal = JOURNAL.read().unwrap_or_else(PoisonError::into_inner);
+	let Some(sock) = &*journal else {
+		return;
+	};
+	let priority = match level {
+		Level::Warning => "4",
+		Level::Error => "3",
+	};
+	let mut buf = Vec::new();
+	field(&mut buf, "PRIORITY", priority);
+	/* Like syslog(3), the journal identifies programs by their base name */
+	let progname = progname.to_string();
+	let ident = Path::new(&progname)
+		.file_name()
+		.and_then(OsStr::to_str)
+		.unwrap_or(&progname);
+	field(&mut buf, "SYSLOG_IDENTIFIER", ident);
+	field(&mut buf, "MESSAGE", &args.to_string());
+	let _ = sock.send(&buf);
+}
+
+/* Append a field to a journal entry.  Values containing newlines must be
+   written as a length-prefixed binary blob. */
+fn field(buf: &mut Vec<u8>, name: &str, value: &str) {
+	buf.extend_from_slice(name.as_bytes());
+	if value.contains('\n') {
+		buf.push(b'\n');
+		buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+	} else {
+		buf.push(b'=');
+	}
+	buf.extend_from_slice(value.as_bytes());
+	buf.push(b'\n');
+}
@@ -1,38 +1,555 @@
 #![doc = include_str!("../README.md")]
+#![allow(clippy::tabs_in_doc_comments)]
+/* The documentation refers to items which only exist with the “full” feature */
+#![cfg_attr(not(feature = "full"), allow(rustdoc::broken_intra_doc_links))]
 
+#[cfg(feature = "full")]
+mod config;
+mod emit;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+mod journald;
+
+use std::error::Error;
+use std::fmt;
+use std::process::{ExitCode, Termination};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "full")]
+pub use config::*;
+pub use emit::*;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use journald::{__enable_journald_at, enable_journald};
 #[doc(hidden)]
 pub use std::{env, process};
 
+/* The exit code for an error given to require!() and friends.  A FatalError
+   goes through the mapper set with set_exit_code_from_error(), while
+   everything else exits with 1.  As __exit_code!() calls (&e).__exit_code(),
+   method resolution prefers __ExitCodeError, which is implemented for
+   FatalError itself, over __ExitCodeFallback, which needs an extra reference.
+
+   The mapper is given a &(dyn Error + 'static), so it can only be called for
+   errors known to be 'static.  A blanket implementation bounded on 'static
+   doesn’t do that: lifetimes are ignored when picking the method, so borrowing
+   errors such as a PoisonError<MutexGuard<'_, T>> would pick it and then fail
+   to borrow check.  FatalError has no lifetimes, so it is always 'static. */
+
+#[doc(hidden)]
+pub trait __ExitCodeError {
+	fn __exit_code(&self) -> i32;
+}
+
+impl __ExitCodeError for FatalError {
+	fn __exit_code(&self) -> i32 {
+		exit_code_from_error(&*self.0)
+	}
+}
+
+#[cfg(feature = "full")]
+fn exit_code_from_error(e: &(dyn Error + 'static)) -> i32 {
+	config::exit_code_from_error(e)
+}
+
+#[cfg(not(feature = "full"))]
+fn exit_code_from_error(_: &(dyn Error + 'static)) -> i32 {
+	1
+}
+
+#[doc(hidden)]
+pub trait __ExitCodeFallback {
+	fn __exit_code(&self) -> i32;
+}
+
+impl<E> __ExitCodeFallback for &E {
+	fn __exit_code(&self) -> i32 {
+		1
+	}
+}
+
+/* The exit code for the error value e, used by every macro exiting on an
+   error */
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __exit_code {
+	($e:expr) => {{
+		#[allow(unused_imports)]
+		use $crate::{__ExitCodeError as _, __ExitCodeFallback as _};
+		(&$e).__exit_code()
+	}};
+}
+
+#[doc(hidden)]
+pub trait __RequireDbg<T> {
+	fn __require_dbg(self, expr: &str, ctx: Option<fmt::Arguments>) -> T;
+}
+
+impl<T, E: fmt::Display> __RequireDbg<T> for Result<T, E> {
+	fn __require_dbg(self, expr: &str, ctx: Option<fmt::Arguments>) -> T {
+		match (self, ctx) {
+			(Ok(v), _) => v,
+			(Err(e), Some(ctx)) => err_fmt(1, format_args!("{}: {expr}: {e}", __Context(ctx))),
+			(Err(e), None) => err_fmt(1, format_args!("{expr}: {e}")),
+		}
+	}
+}
+
+impl<T> __RequireDbg<T> for Option<T> {
+	fn __require_dbg(self, expr: &str, ctx: Option<fmt::Arguments>) -> T {
+		match (self, ctx) {
+			(Some(v), _) => v,
+			(None, Some(ctx)) => err_fmt(1, format_args!("{}: {expr}", __Context(ctx))),
+			(None, None) => err_fmt(1, format_args!("{expr}: None")),
+		}
+	}
+}
+
+/// Return the name of an exit code defined by [`sysexits(3)`].
+///
+/// The codes defined by `<sysexits.h>` are conventionally used by programs to
+/// describe why they failed.  This function returns the name of the given code
+/// — such as `"EX_USAGE"` for 64 — or [`None`] if it isn’t one of them.  This is
+/// useful for annotating exit codes in summaries and other human-readable
+/// output.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(cerm::describe_exit_code(64), Some("EX_USAGE"));
+/// assert_eq!(cerm::describe_exit_code(42), None);
+/// ```
+///
+/// [`sysexits(3)`]: https://man.openbsd.org/sysexits.3
+pub fn describe_exit_code(code: i32) -> Option<&'static str> {
+	Some(match code {
+		0 => "EX_OK",
+		64 => "EX_USAGE",
+		65 => "EX_DATAERR",
+		66 => "EX_NOINPUT",
+		67 => "EX_NOUSER",
+		68 => "EX_NOHOST",
+		69 => "EX_UNAVAILABLE",
+		70 => "EX_SOFTWARE",
+		71 => "EX_OSERR",
+		72 => "EX_OSFILE",
+		73 => "EX_CANTCREAT",
+		74 => "EX_IOERR",
+		75 => "EX_TEMPFAIL",
+		76 => "EX_PROTOCOL",
+		77 => "EX_NOPERM",
+		78 => "EX_CONFIG",
+		_ => return None,
+	})
+}
+
+/// A return type for `main` that reports a fatal diagnostic.
+///
+/// A `Fatal` either holds a successful value, or a diagnostic message and an
+/// exit code.  When returned from `main`, the diagnostic is printed as with
+/// [`err!`] and the program exits with the given code; a successful value is
+/// reported as it normally would be.
+///
+/// Unlike the [`err!`] family of macros, which exit the program immediately,
+/// returning a `Fatal` from `main` unwinds the stack normally, so destructors
+/// are run before the program exits.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::env;
+/// use cerm::Fatal;
+///
+/// fn main() -> Fatal {
+/// 	if env::args().len() != 2 {
+/// 		return Fatal::new(64, "usage: mytool file");
+/// 	}
+/// 	/* … */
+/// 	Fatal::ok(())
+/// }
+/// ```
+///
+/// The diagnostic is printed as an error when the `Fatal` is reported.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use std::process::{ExitCode, Termination};
+/// use cerm::{Fatal, OutputFormat};
+///
+/// assert_eq!(Fatal::<()>::new(2, "bad").report(), ExitCode::from(2));
+/// assert_eq!(buf.take(), b"prog: bad\n");
+/// assert_eq!(Fatal::ok(()).report(), ExitCode::SUCCESS);
+/// assert!(buf.take().is_empty());
+///
+/// cerm::set_fatal_label("fatal:");
+/// cerm::set_output_format(OutputFormat::GithubActions);
+/// Fatal::<()>::new(2, "bad").report();
+/// assert_eq!(buf.take(), b"::error::prog: fatal: bad\n");
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Fatal<T: Termination = ()>(Result<T, (u8, String)>);
+
+impl<T: Termination> Fatal<T> {
+	/// Create a `Fatal` reporting the given diagnostic and exiting with the
+	/// given code.
+	pub fn new(code: u8, message: impl Into<String>) -> Self {
+		Self(Err((code, message.into())))
+	}
+
+	/// Create a `Fatal` holding a successful value.
+	pub fn ok(v: T) -> Self {
+		Self(Ok(v))
+	}
+}
+
+impl<T: Termination> From<T> for Fatal<T> {
+	fn from(v: T) -> Self {
+		Self::ok(v)
+	}
+}
+
+impl<T: Termination> Termination for Fatal<T> {
+	fn report(self) -> ExitCode {
+		match self.0 {
+			Ok(v) => v.report(),
+			Err((code, message)) => {
+				emit::report_err(code.into(), format_args!("{message}"));
+				ExitCode::from(code)
+			}
+		}
+	}
+}
+
+#[doc(hidden)]
+pub fn __require_env(var: &str, ctx: Option<fmt::Arguments>) -> String {
+	match (env::var(var), ctx) {
+		(Ok(v), _) => v,
+		(Err(e), Some(ctx)) => err_fmt(exit_code_from_error(&e), ctx),
+		(Err(e @ env::VarError::NotPresent), None) => err_fmt(
+			exit_code_from_error(&e),
+			format_args!("environment variable {var} is not set"),
+		),
+		(Err(e), None) => err_fmt(exit_code_from_error(&e), format_args!("{var}: {e}")),
+	}
+}
+
+#[doc(hidden)]
+pub fn __require_env_parse<T>(var: &str) -> T
+where
+	T: FromStr,
+	T::Err: fmt::Display,
+{
+	let v = __require_env(var, None);
+	match v.parse() {
+		Ok(v) => v,
+		Err(e) => err_fmt(
+			1,
+			format_args!("invalid value “{v}” for environment variable {var}: {e}"),
+		),
+	}
+}
+
+/// An error type that can hold any error, for use with [`ResultExt`].
+///
+/// Any type implementing [`Error`] can be converted into a `FatalError`, so
+/// functions returning `Result<T, FatalError>` can use the `?` operator on
+/// whatever errors they encounter, leaving it up to the caller to report them
+/// with [`ResultExt::or_die()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs;
+/// use cerm::{FatalError, ResultExt};
+///
+/// fn read_config() -> Result<String, FatalError> {
+/// 	let s = fs::read_to_string("config.toml")?;
+/// 	Ok(s)
+/// }
+///
+/// let conf = read_config().or_die();
+/// ```
+pub struct FatalError(Box<dyn Error + Send + Sync>);
+
+impl FatalError {
+	/// Return the underlying error.
+	pub fn into_inner(self) -> Box<dyn Error + Send + Sync> {
+		self.0
+	}
+}
+
+impl<E: Error + Send + Sync + 'static> From<E> for FatalError {
+	fn from(e: E) -> Self {
+		Self(Box::new(e))
+	}
+}
+
+impl fmt::Debug for FatalError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(&self.0, f)
+	}
+}
+
+impl fmt::Display for FatalError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(&self.0, f)
+	}
+}
+
+/// Extension methods for reporting errors in [`std::result::Result`]s.
+///
+/// These methods are the method-chaining counterparts to [`require!`] and
+/// `require!((res, code))`.  They are implemented for any
+/// [`std::result::Result`] whose error type implements
+/// [`Display`](fmt::Display), which includes all [`Error`]s as well as
+/// [`FatalError`].
+pub trait ResultExt<T> {
+	/// Return the contained [`Result::Ok`] value, or print the error and exit.
+	///
+	/// In the case of `Err(e)`, [`err!`] is called with the format string
+	/// `"{e}"`.  The function set with [`set_exit_code_from_error()`] is not
+	/// consulted, even for a [`FatalError`]; use [`require!`] for that.
+	///
+	/// # Panics
+	///
+	/// Calls [`err!`] which may panic if it fails.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # #[cfg(feature = "full")] {
+	/// use std::fs::File;
+	/// use cerm::ResultExt;
+	///
+	/// let f = File::open("Cargo.toml").or_die();
+	///
+	/// let res = cerm::catch_fatal(|| "x".parse::<i32>().or_die());
+	/// assert_eq!(res.unwrap_err().message, "invalid digit found in string");
+	/// # }
+	/// ```
+	fn or_die(self) -> T;
+
+	/// Return the contained [`Result::Ok`] value, or print the error and exit
+	/// with the given code.
+	///
+	/// In the case of `Err(e)`, [`err_code!`] is called with the given code and
+	/// the format string `"{e}"`.
+	///
+	/// # Panics
+	///
+	/// Calls [`err_code!`] which may panic if it fails.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # #[cfg(feature = "full")] {
+	/// use std::fs::File;
+	/// use cerm::ResultExt;
+	///
+	/// let res = cerm::catch_fatal(|| File::open("/nonexistent").or_die_code(66));
+	/// assert_eq!(res.unwrap_err().code, 66);
+	/// # }
+	/// ```
+	fn or_die_code(self, code: i32) -> T;
+}
+
+impl<T, E: fmt::Display> ResultExt<T> for Result<T, E> {
+	fn or_die(self) -> T {
+		self.or_die_code(1)
+	}
+
+	fn or_die_code(self, code: i32) -> T {
+		match self {
+			Ok(v) => v,
+			Err(e) => err_fmt(code, format_args!("{e}")),
+		}
+	}
+}
+
+/// Extension methods for reporting missing values in [`std::option::Option`]s.
+///
+/// These methods are the method-chaining counterparts to the
+/// [`std::option::Option`] form of [`require!`].  As an [`Option::None`] carries
+/// no error to describe, the message to print is given as [`fmt::Arguments`],
+/// which can be created without allocating using [`format_args!`].
+pub trait OptionExt<T> {
+	/// Return the contained [`Option::Some`] value, or print the given message
+	/// and exit.
+	///
+	/// In the case of [`Option::None`], [`err!`] is called with the given
+	/// message.
+	///
+	/// # Panics
+	///
+	/// Calls [`err!`] which may panic if it fails.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use std::process::{Command, Stdio};
+	/// use cerm::{OptionExt, ResultExt};
+	///
+	/// let cmd = "cat";
+	/// let mut child = Command::new(cmd).stdin(Stdio::piped()).spawn().or_die();
+	/// let ci = child.stdin.take().or_die(format_args!("no stdin for {cmd}"));
+	/// ```
+	///
+	/// The message is printed and the program exits.
+	///
+	/// ```
+	/// # #[cfg(feature = "full")] {
+	/// use cerm::OptionExt;
+	///
+	/// let name = "bin";
+	/// let res = cerm::catch_fatal(|| None::<()>.or_die(format_args!("no {name}")));
+	/// assert_eq!(res.unwrap_err().message, "no bin");
+	/// # }
+	/// ```
+	fn or_die(self, args: fmt::Arguments) -> T;
+
+	/// Return the contained [`Option::Some`] value, or print the given message
+	/// and exit with the given code.
+	///
+	/// In the case of [`Option::None`], [`err_code!`] is called with the given
+	/// code and message.
+	///
+	/// # Panics
+	///
+	/// Calls [`err_code!`] which may panic if it fails.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # #[cfg(feature = "full")] {
+	/// use std::env;
+	/// use cerm::OptionExt;
+	///
+	/// let res = cerm::catch_fatal(|| {
+	/// 	env::args().nth(42).or_die_code(64, format_args!("usage: mytool file"))
+	/// });
+	/// assert_eq!(res.unwrap_err().code, 64);
+	/// # }
+	/// ```
+	fn or_die_code(self, code: i32, args: fmt::Arguments) -> T;
+}
+
+impl<T> OptionExt<T> for Option<T> {
+	fn or_die(self, args: fmt::Arguments) -> T {
+		self.or_die_code(1, args)
+	}
+
+	fn or_die_code(self, code: i32, args: fmt::Arguments) -> T {
+		match self {
+			Some(v) => v,
+			None => err_fmt(code, args),
+		}
+	}
+}
+
+/// How long [`require_retry!`] waits between attempts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+	/// Wait the same amount of time after every attempt.
+	Constant(Duration),
+	/// Wait the given amount of time multiplied by the number of failed
+	/// attempts so far.
+	Linear(Duration),
+	/// Wait the given amount of time after the first failed attempt, and
+	/// double it after every subsequent one.
+	Exponential(Duration),
+}
+
+impl Backoff {
+	fn delay(self, failures: u32) -> Duration {
+		match self {
+			Self::Constant(d) => d,
+			Self::Linear(d) => d.saturating_mul(failures),
+			Self::Exponential(d) => {
+				d.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+			}
+		}
+	}
+}
+
+impl From<Duration> for Backoff {
+	fn from(d: Duration) -> Self {
+		Self::Constant(d)
+	}
+}
+
+#[doc(hidden)]
+pub fn __retry<T, E>(n: u32, backoff: Backoff, f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+	__retry_if(n, backoff, f, |_| true)
+}
+
+#[doc(hidden)]
+pub fn __retry_if<T, E>(
+	n: u32,
+	backoff: Backoff,
+	mut f: impl FnMut() -> Result<T, E>,
+	mut retry: impl FnMut(&E) -> bool,
+) -> Result<T, E> {
+	let mut failures = 0;
+	loop {
+		match f() {
+			Err(e) if failures + 1 < n && retry(&e) => {
+				failures += 1;
+				thread::sleep(backoff.delay(failures));
+			}
+			res => return res,
+		}
+	}
+}
+
 /// Print a diagnostic message to the standard error and exit with a given code.
 ///
 /// This macro is analagous to the BSD [`errx(3)`] C function.  It takes at a
 /// minimum two arguments.  The first argument is the code with passed to
 /// [`std::process::exit()`] with which we exit the program.  The second and
-/// optional additional arguments are passed to the [`eprintln!`] macro.
+/// optional additional arguments are passed to the [`format_args!`] macro.
 ///
 /// When invoked, the given format and arguments are printed to the standard
 /// error, prepended by the string `"progname: "`, where `progname` is the
 /// program name as defined by the first element in [`std::env::args`].  If for
 /// whatever reason no such element exists (which is possible), we default to
-/// simply using `"Error"` as the program name.
+/// simply using `"Error"` as the program name.  The program name can also be
+/// set explicitly with [`set_progname()`].  The diagnostic is terminated by the
+/// current [`record_terminator()`], which by default is the current
+/// [`line_ending()`].  Before exiting, the hook set with
+/// [`set_async_flush_hook()`] is run, if any.
+///
+/// If the [`exit_behavior()`] is [`ExitBehavior::Panic`], then instead of
+/// exiting we panic with a [`FatalExit`] carrying the exit code and message.
+/// If [`set_abort_on_error()`] is enabled, we abort instead of exiting.
+///
+/// The diagnostic may be preceded by an alert; see [`set_alert_on_error()`].
+/// A fatal diagnostic raised while this one is being handled exits with
+/// [`REENTRANT_EXIT_CODE`] instead.
+///
+/// In debug builds, a diagnostic whose message is empty is preceded by a
+/// warning saying so, as it tells the user nothing.  To exit without printing
+/// anything, use [`exit_quiet!`] instead.
 ///
 /// If you do not care about specifying a specific exit code and are fine with
 /// simply defaulting to `1`, you may prefer to use [`err!`].
 ///
 /// # Panics
 ///
-/// Calls [`eprint!`], [`eprintln!`], and [`std::env::args`] which may all panic
-/// if they fail.
+/// Calls [`eprint!`] and [`std::env::args`] which may both panic if they fail.
 ///
 /// # Examples
 ///
 /// Print a diagnostic in the form `progname: path: error`, and then exit the
 /// program with an exit status of 2.
 ///
-/// ```
+/// ```no_run
 /// use std::fs;
 /// use cerm::err_code;
 ///
+/// # let path = "foo";
 /// let res = match fs::create_dir(&path) {
 /// 	Ok(v) => v,
 /// 	Err(e) => { err_code!(2, "{}: {}", path, e); }
@@ -43,9 +560,7 @@ pub use std::{env, process};
 #[macro_export]
 macro_rules! err_code {
 	($code:expr, $($fmt:tt)+) => {
-		eprint!("{}: ", $crate::env::args().next().unwrap_or("Error".into()));
-		eprintln!($($fmt)+);
-		$crate::process::exit($code);
+		$crate::err_fmt($code, format_args!($($fmt)+));
 	};
 }
 
@@ -64,10 +579,11 @@ macro_rules! err_code {
 /// Print a diagnostic in the form `progname: path: error`, and then exit the
 /// program with an exit status of 1.
 ///
-/// ```
+/// ```no_run
 /// use std::fs;
-/// use cerm::err_code;
+/// use cerm::err;
 ///
+/// # let path = "foo";
 /// let res = match fs::create_dir(&path) {
 /// 	Ok(v) => v,
 /// 	Err(e) => { err!("{}: {}", path, e); }
@@ -75,6 +591,10 @@ macro_rules! err_code {
 /// ```
 #[macro_export]
 macro_rules! err {
+	/* A lone literal is rendered without going through the formatter */
+	($lit:literal) => {
+		$crate::err_fmt(1, format_args!($lit));
+	};
 	($($fmt:tt)+) => {
 		$crate::err_code!(1, $($fmt)+);
 	}
@@ -84,27 +604,30 @@ macro_rules! err {
 ///
 /// This macro is analagous to the BSD [`warnx(3)`] C function.  It takes the
 /// same arguments one would pass to a macro like [`println!`].  In fact, the
-/// arguments are passed directly to [`eprintln]`.
+/// arguments are passed directly to [`format_args!`].
 ///
 /// When invoked, the given format and arguments are printed to the standard
 /// error, prepended by the string `"progname: "`, where `progname` is the
 /// program name as defined by the first element in [`std::env::args`].  If for
 /// whatever reason no such element exists (which is possible), we default to
-/// simply using `"Error"` as the program name.
+/// simply using `"Error"` as the program name.  The program name can also be
+/// set explicitly with [`set_progname()`].  The diagnostic is terminated by the
+/// current [`record_terminator()`], which by default is the current
+/// [`line_ending()`].
 ///
 /// # Panics
 ///
-/// Calls [`eprint!`], [`eprintln!`], and [`std::env::args`] which may all panic
-/// if they fail.
+/// Calls [`eprint!`] and [`std::env::args`] which may both panic if they fail.
 ///
 /// # Examples
 ///
 /// Print a diagnostic in the form `progname: path: error`
 ///
-/// ```
+/// ```no_run
 /// use std::fs;
 /// use cerm::warn;
 ///
+/// # let path = "foo";
 /// let res = match fs::create_dir(&path) {
 /// 	Ok(v) => v,
 /// 	Err(e) => {
@@ -114,12 +637,250 @@ macro_rules! err {
 /// };
 /// ```
 ///
-/// [`errx(3)`]: https://man.openbsd.org/err.3
+/// A message without arguments is printed exactly as if it were formatted.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn;
+///
+/// warn!("x");
+/// assert_eq!(buf.take(), b"prog: x\n");
+/// warn!("{}", "x");
+/// assert_eq!(buf.take(), b"prog: x\n");
+///
+/// let x = 42;
+/// warn!("{x}");
+/// assert_eq!(buf.take(), b"prog: 42\n");
+/// warn!("{{x}}");
+/// assert_eq!(buf.take(), b"prog: {x}\n");
+/// # }
+/// ```
+///
+/// [`warnx(3)`]: https://man.openbsd.org/warnx.3
 #[macro_export]
 macro_rules! warn {
+	/* A lone literal is rendered without going through the formatter.  It
+	   still goes through format_args!() to capture variables and unescape
+	   braces, but then fmt::Arguments::as_str() hands it back as is. */
+	($lit:literal) => {
+		$crate::warn_fmt(format_args!($lit));
+	};
+	($($fmt:tt)+) => {
+		$crate::warn_fmt(format_args!($($fmt)+));
+	};
+}
+
+/// The same thing as [`err!`], but the message is tagged with a target.
+///
+/// The first argument is the target — typically the name of the subsystem
+/// emitting the diagnostic — which is printed in square brackets between the
+/// program name and the message.  The remaining arguments are the same as those
+/// of [`err!`].
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Print a diagnostic in the form `progname: [net] error`, and then exit the
+/// program with an exit status of 1.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+/// use cerm::err_target;
+///
+/// let sock = match TcpStream::connect("localhost:8080") {
+/// 	Ok(v) => v,
+/// 	Err(e) => { err_target!("net", "{e}"); }
+/// };
+/// ```
+///
+/// The target comes right after the program name.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::err_target;
+///
+/// let res = cerm::catch_fatal(|| { err_target!("net", "connection refused"); });
+/// assert_eq!(res.unwrap_err().message, "[net] connection refused");
+/// assert_eq!(buf.take(), b"prog: [net] connection refused\n");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! err_target {
+	($target:expr, $($fmt:tt)+) => {
+		$crate::err!("[{}] {}", $target, format_args!($($fmt)+));
+	};
+}
+
+/// The same thing as [`warn!`], but the message is tagged with a target.
+///
+/// The first argument is the target — typically the name of the subsystem
+/// emitting the diagnostic — which is printed in square brackets between the
+/// program name and the message.  The remaining arguments are the same as those
+/// of [`warn!`].
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Print a diagnostic in the form `progname: [config] message`.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_target;
+///
+/// warn_target!("config", "no configuration file found; using defaults");
+/// assert_eq!(
+/// 	buf.take(),
+/// 	b"prog: [config] no configuration file found; using defaults\n",
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_target {
+	($target:expr, $($fmt:tt)+) => {
+		$crate::warn!("[{}] {}", $target, format_args!($($fmt)+));
+	};
+}
+
+/// Print a deprecation notice to the standard error, but only once per key.
+///
+/// The first argument is a key identifying the deprecated feature.  The
+/// remaining arguments are the same as those of [`warn!`].  The first time a
+/// given key is seen the message is printed in the form
+/// `"progname: deprecated: message"`; all subsequent invocations with the same
+/// key print nothing, regardless of where they are invoked from.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Warn about deprecated flags, no matter how many times they were given.
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_deprecated;
+///
+/// for arg in ["-x", "-x", "-v", "-z"] {
+/// 	match arg {
+/// 		"-x" => warn_deprecated!("-x", "the -x flag is deprecated; use -y instead"),
+/// 		"-z" => warn_deprecated!("-z", "the -z flag is deprecated"),
+/// 		_ => (),
+/// 	}
+/// }
+/// assert_eq!(
+/// 	String::from_utf8(buf.take()).unwrap(),
+/// 	"prog: deprecated: the -x flag is deprecated; use -y instead\n\
+/// 	 prog: deprecated: the -z flag is deprecated\n",
+/// );
+/// ```
+///
+/// A sink may itself report something deprecated.
+///
+/// ```
+/// use cerm::{warn_deprecated, Diagnostic, StructuredSink};
+///
+/// struct Sink;
+/// impl StructuredSink for Sink {
+/// 	fn emit(&mut self, _: &Diagnostic) {
+/// 		warn_deprecated!("sink", "this sink is deprecated");
+/// 	}
+/// }
+///
+/// cerm::set_structured_sink(Box::new(Sink));
+/// warn_deprecated!("-x", "the -x flag is deprecated");
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! warn_deprecated {
+	($key:expr, $($fmt:tt)+) => {
+		$crate::__warn_deprecated(&$key, format_args!($($fmt)+));
+	};
+}
+
+/// The same thing as [`warn!`], but the message is printed to the standard
+/// output.
+///
+/// This is useful for filter-style programs whose notices are part of their
+/// regular output, such as a `grep`-like tool reporting that a binary file
+/// matched.  Everything other than the output stream is the same as with
+/// [`warn!`], except that notices are not held back by
+/// [`with_grouped_output!`], and are never written to the descriptor set with
+/// [`set_status_fd()`].
+///
+/// # Panics
+///
+/// Calls [`print!`] and [`std::env::args`] which may both panic if they fail.
+///
+/// # Examples
+///
+/// Print a notice in the form `progname: binary file matches` to the standard
+/// output.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::outln;
+///
+/// outln!("binary file matches");
+/// # assert_eq!(buf.take(), b"prog: binary file matches\n");
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! outln {
 	($($fmt:tt)+) => {
-		eprint!("{}: ", $crate::env::args().next().unwrap_or("Error".into()));
-		eprintln!($($fmt)+);
+		$crate::outln_fmt(format_args!($($fmt)+));
+	};
+}
+
+/// Require that a condition holds at compile time.
+///
+/// This macro is the compile-time counterpart to [`require!`].  It takes a
+/// constant boolean expression and a string literal, and expands to an inline
+/// `const` block asserting the condition.  If the condition is false, the given
+/// message is reported as a compilation error.
+///
+/// As the assertion is evaluated at compile time, the condition may only refer
+/// to constants.
+///
+/// # Examples
+///
+/// Make sure that a table of builtin commands is not empty.
+///
+/// ```
+/// use cerm::const_require;
+///
+/// const BUILTINS: &[&str] = &["cd", "exit"];
+/// const_require!(!BUILTINS.is_empty(), "no builtins defined");
+/// ```
+///
+/// If the condition is false, the program fails to compile.
+///
+/// ```compile_fail,E0080
+/// use cerm::const_require;
+///
+/// const BUILTINS: &[&str] = &[];
+/// const_require!(!BUILTINS.is_empty(), "no builtins defined");
+/// ```
+#[macro_export]
+macro_rules! const_require {
+	($cond:expr, $msg:literal $(,)?) => {
+		const { ::core::assert!($cond, $msg) }
 	};
 }
 
@@ -140,6 +901,12 @@ macro_rules! warn {
 /// Therefore you the user also need to provide as additional arguments the same
 /// parameters you would pass to an invokation of [`err!`].
 ///
+/// To exit with a code other than `1` when a [`std::result::Result`] is an
+/// error, pass the [`std::result::Result`] and the code together as a
+/// parenthesized pair, as in `require!((res, code))`.  The error is then
+/// reported with [`err_code!`] instead.  The exit code for a [`FatalError`]
+/// can also be chosen with [`set_exit_code_from_error()`].
+///
 /// # Panics
 ///
 /// Calls [`err!`] which may panic if it fails.
@@ -150,9 +917,12 @@ macro_rules! warn {
 /// result of `stdin.take()` is [`Option::None` ]then print the given diagnostic
 /// message and exit the program using [`err!`].
 ///
-/// ```
+/// ```no_run
 /// use cerm::require;
 ///
+/// # use std::process::{Command, Stdio};
+/// # let cmd = std::ffi::OsString::from("cat");
+/// # let mut child = Command::new(&cmd).stdin(Stdio::piped()).spawn().unwrap();
 /// let ci = require!(
 /// 	child.stdin.take(),
 /// 	"Failed to open stdin of “{}”",
@@ -165,23 +935,2034 @@ macro_rules! warn {
 /// `child.wait()` fails.  Notice how because `child.wait()` returns a
 /// [`std::result::Result`], we only specify one argument.
 ///
-/// ```
+/// ```no_run
 /// use cerm::require;
 ///
+/// # let mut child = std::process::Command::new("true").spawn().unwrap();
 /// require!(child.wait());
 /// ```
+///
+/// Read a file, exiting with a code of 66 if that fails.  The parenthesized
+/// pair is not confused with the other forms.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::{fs, panic};
+/// use cerm::{require, ExitBehavior, FatalExit};
+///
+/// cerm::set_thread_exit_behavior(Some(ExitBehavior::Panic));
+///
+/// let res = panic::catch_unwind(|| require!((fs::read("/nonexistent"), 66)));
+/// let fe = res.unwrap_err().downcast::<FatalExit>().unwrap();
+/// assert_eq!(fe.code, 66);
+///
+/// let res = panic::catch_unwind(|| require!(fs::read("/nonexistent")));
+/// assert_eq!(res.unwrap_err().downcast::<FatalExit>().unwrap().code, 1);
+///
+/// let res = panic::catch_unwind(|| require!(None::<()>, "nothing"));
+/// assert_eq!(res.unwrap_err().downcast::<FatalExit>().unwrap().message, "nothing");
+///
+/// assert_eq!(require!((Ok::<_, String>(42), 66)), 42);
+/// # }
+/// ```
+///
+/// Errors may borrow from their surroundings, such as those of a poisoned
+/// lock.
+///
+/// ```
+/// use std::sync::Mutex;
+/// use cerm::require;
+///
+/// let m = Mutex::new(0);
+/// *require!(m.lock()) += 1;
+/// assert_eq!(*require!(m.lock()), 1);
+/// ```
 #[macro_export]
 macro_rules! require {
+	(($e:expr, $code:expr)) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => { $crate::err_code!($code, "{e}"); },
+		}
+	};
 	($e:expr) => {
 		match $e {
 			Ok(v) => v,
-			Err(e) => { err!("{e}"); },
+			Err(e) => {
+				$crate::err_code!($crate::__exit_code!(e), "{e}");
+			},
+		}
+	};
+	($e:expr, $($fmt:tt)+) => {
+		match $e {
+			Some(v) => v,
+			None => { $crate::err!($($fmt)+); },
 		}
 	};
+}
+
+/// The same thing as [`require!`], but the diagnostic includes the expression.
+///
+/// This macro works like [`require!`], except that the source text of the
+/// checked expression is included in the diagnostic, similar to how [`assert!`]
+/// reports the failing condition.  Unlike [`require!`] it accepts both
+/// [`std::result::Result`]s and [`std::option::Option`]s with or without
+/// additional arguments.
+///
+/// Without additional arguments, a failed [`std::result::Result`] prints
+/// `"progname: expr: error"` and a failed [`std::option::Option`] prints
+/// `"progname: expr: None"`.  With additional arguments — the same ones you
+/// would pass to [`err!`] — the formatted message is printed before the
+/// expression, as in `"progname: message: expr"`.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Take a child process’ standard input, and report the expression that failed
+/// if there is none.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::panic;
+/// use std::process::Command;
+/// use cerm::{require_dbg, ExitBehavior, FatalExit};
+///
+/// cerm::set_thread_exit_behavior(Some(ExitBehavior::Panic));
+/// let mut child = Command::new("true").spawn().unwrap();
+/// let res = panic::catch_unwind(move || {
+/// 	require_dbg!(child.stdin.take(), "could not open file");
+/// });
+/// let fe = res.unwrap_err().downcast::<FatalExit>().unwrap();
+/// assert_eq!(fe.message, "could not open file: child.stdin.take()");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_dbg {
+	($e:expr) => {
+		$crate::__RequireDbg::__require_dbg($e, ::core::stringify!($e), None)
+	};
 	($e:expr, $($fmt:tt)+) => {
+		$crate::__RequireDbg::__require_dbg(
+			$e,
+			::core::stringify!($e),
+			Some(format_args!($($fmt)+)),
+		)
+	};
+}
+
+/// The same thing as [`require!`], but a cleanup closure is run on failure.
+///
+/// This macro takes the same arguments as [`require!`], except that the second
+/// argument is a closure taking no arguments.  If the expression is an error or
+/// [`Option::None`], the closure is called before the diagnostic is printed and
+/// the program exits.  This is useful to release a resource that was acquired
+/// right before the fallible call, such as removing a temporary file.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Remove a temporary file if it can’t be written to.
+///
+/// ```no_run
+/// use std::fs::{self, File};
+/// use std::io::Write;
+/// use cerm::{require, require_cleanup};
+///
+/// let mut f = require!(File::create("out.tmp"));
+/// require_cleanup!(f.write_all(b"data"), || {
+/// 	let _ = fs::remove_file("out.tmp");
+/// });
+/// ```
+///
+/// The cleanup runs before the program exits.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::cell::{Cell, RefCell};
+/// use cerm::require_cleanup;
+///
+/// let cleaned = Cell::new(false);
+/// let res = cerm::catch_fatal(|| {
+/// 	require_cleanup!(None::<()>, || cleaned.set(true), "no value");
+/// });
+/// assert!(cleaned.get());
+/// assert_eq!(res.unwrap_err().message, "no value");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_cleanup {
+	($e:expr, $cleanup:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => {
+				($cleanup)();
+				$crate::err_code!($crate::__exit_code!(e), "{e}");
+			},
+		}
+	};
+	($e:expr, $cleanup:expr, $($fmt:tt)+) => {
 		match $e {
 			Some(v) => v,
-			None => { err!($($fmt)+); },
+			None => {
+				($cleanup)();
+				$crate::err!($($fmt)+);
+			},
+		}
+	};
+}
+
+/// The same thing as [`require!`], but the error is printed with [`Debug`].
+///
+/// This macro takes a single [`std::result::Result`] and, in the case of
+/// `Err(e)`, calls [`err!`] with the format string `"{e:?}"` instead of
+/// `"{e}"`.  This is useful for error types that only implement [`Debug`], or
+/// whose [`Debug`] representation is more useful than their
+/// [`Display`](std::fmt::Display) one.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_dbg_err;
+///
+/// #[derive(Debug)]
+/// struct BadMagic(u32);
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_dbg_err!(Err::<(), _>(BadMagic(0xDEADBEEF)));
+/// });
+/// assert_eq!(res.unwrap_err().message, "BadMagic(3735928559)");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_dbg_err {
+	($e:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => { $crate::err_code!($crate::__exit_code!(e), "{e:?}"); },
+		}
+	};
+}
+
+/// The same thing as [`warn!`], but the message is about a file.
+///
+/// The first argument is the path of the file, which is printed between the
+/// program name and the message as in `"progname: path: message"`.  The
+/// remaining arguments are the same as those of [`warn!`].  If enabled with
+/// [`set_hyperlinks()`], the path is rendered as a terminal hyperlink.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::warn_file;
+///
+/// warn_file!("Cargo.toml", "no license specified");
+/// ```
+#[macro_export]
+macro_rules! warn_file {
+	($path:expr, $($fmt:tt)+) => {
+		$crate::warn!(
+			"{}: {}",
+			$crate::__FileLink(::std::path::Path::new(&$path)),
+			format_args!($($fmt)+),
+		);
+	};
+}
+
+/// The same thing as [`err!`], but the message is about a file.
+///
+/// The first argument is the path of the file, which is printed between the
+/// program name and the message as in `"progname: path: message"`.  The
+/// remaining arguments are the same as those of [`err!`].  If enabled with
+/// [`set_hyperlinks()`], the path is rendered as a terminal hyperlink.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs;
+/// use cerm::err_file;
+///
+/// let path = "config.toml";
+/// let conf = match fs::read_to_string(path) {
+/// 	Ok(v) => v,
+/// 	Err(e) => { err_file!(path, "{e}"); }
+/// };
+/// ```
+#[macro_export]
+macro_rules! err_file {
+	($path:expr, $($fmt:tt)+) => {
+		$crate::err!(
+			"{}: {}",
+			$crate::__FileLink(::std::path::Path::new(&$path)),
+			format_args!($($fmt)+),
+		);
+	};
+}
+
+/// The same thing as [`require!`], but the exit code is derived from the error.
+///
+/// This macro takes a [`std::result::Result`] and a closure.  In the case of
+/// `Err(e)`, the closure is called with a reference to the error and
+/// [`err_code!`] is called with the code it returns and the format string
+/// `"{e}"`.  The closure is only called on failure.
+///
+/// # Panics
+///
+/// Calls [`err_code!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Exit with a different code depending on what went wrong.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::fmt;
+/// use cerm::require_map_code;
+///
+/// enum Error {
+/// 	Usage,
+/// 	NoInput(String),
+/// }
+///
+/// impl fmt::Display for Error {
+/// 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// 		match self {
+/// 			Self::Usage => write!(f, "usage: mytool file"),
+/// 			Self::NoInput(s) => write!(f, "{s}: no such file"),
+/// 		}
+/// 	}
+/// }
+///
+/// let code_for = |e: &Error| match e {
+/// 	Error::Usage => 64,
+/// 	Error::NoInput(_) => 66,
+/// };
+///
+/// let res = cerm::catch_fatal(|| require_map_code!(Err::<(), _>(Error::Usage), code_for));
+/// assert_eq!(res.unwrap_err().code, 64);
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_map_code!(Err::<(), _>(Error::NoInput("foo".into())), code_for)
+/// });
+/// let fe = res.unwrap_err();
+/// assert_eq!(fe.code, 66);
+/// assert_eq!(fe.message, "foo: no such file");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_map_code {
+	($e:expr, $f:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => { $crate::err_code!(($f)(&e), "{e}"); },
+		}
+	};
+}
+
+/// The same thing as [`require!`], but a fallible operation is retried.
+///
+/// This macro takes the maximum number of attempts, a [`Backoff`] (or a
+/// [`Duration`](std::time::Duration), which is treated as [`Backoff::Constant`])
+/// and a closure returning a [`std::result::Result`].  The closure is called
+/// until it returns [`Result::Ok`], sleeping between attempts according to the
+/// backoff, and the value is returned.  If the last attempt fails, [`err!`] is
+/// called with the format string `"{e}"` (with `e` being the last error).  The
+/// closure is always called at least once.
+///
+/// This is useful for operations that may fail transiently, such as network
+/// requests or acquiring a lock.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Connect to a server, trying up to 5 times and waiting 100ms, 200ms, 400ms,
+/// and 800ms between attempts.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+/// use std::time::Duration;
+/// use cerm::{require_retry, Backoff};
+///
+/// let sock = require_retry!(5, Backoff::Exponential(Duration::from_millis(100)), || {
+/// 	TcpStream::connect("localhost:8080")
+/// });
+/// ```
+///
+/// An operation that fails twice before succeeding.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::time::Duration;
+/// use cerm::require_retry;
+///
+/// let mut tries = 0;
+/// let n = require_retry!(3, Duration::ZERO, || {
+/// 	tries += 1;
+/// 	if tries < 3 { Err("busy") } else { Ok(tries) }
+/// });
+/// assert_eq!(n, 3);
+///
+/// let res = cerm::catch_fatal(|| require_retry!(2, Duration::ZERO, || Err::<(), _>("busy")));
+/// assert_eq!(res.unwrap_err().message, "busy");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_retry {
+	($n:expr, $backoff:expr, $f:expr) => {
+		$crate::require!($crate::__retry(
+			$n,
+			::core::convert::Into::into($backoff),
+			$f,
+		))
+	};
+}
+
+/// Require that an environment variable is set.
+///
+/// This macro takes the name of an environment variable and returns its value
+/// as a [`String`].  If the variable is not set, [`err!`] is called with a
+/// message in the form `"environment variable VAR is not set"`.  If the value
+/// is not valid Unicode, the message describes that instead.
+///
+/// You may also provide as additional arguments the same parameters you would
+/// pass to an invokation of [`err!`], in which case they are used for the
+/// diagnostic instead.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::env;
+/// use cerm::require_env;
+///
+/// env::set_var("EDITOR", "ed");
+/// assert_eq!(require_env!("EDITOR"), "ed");
+///
+/// env::remove_var("EDITOR");
+/// let res = cerm::catch_fatal(|| require_env!("EDITOR"));
+/// assert_eq!(res.unwrap_err().message, "environment variable EDITOR is not set");
+///
+/// let res = cerm::catch_fatal(|| require_env!("EDITOR", "no editor configured"));
+/// assert_eq!(res.unwrap_err().message, "no editor configured");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_env {
+	($var:expr) => {
+		$crate::__require_env(&$var, None)
+	};
+	($var:expr, $($fmt:tt)+) => {
+		$crate::__require_env(&$var, Some(format_args!($($fmt)+)))
+	};
+}
+
+/// Require that an environment variable is set to a valid value.
+///
+/// This macro works like [`require_env!`], but the value of the variable is
+/// parsed with [`str::parse()`], and [`err!`] is called with a description of
+/// the error if parsing fails.  The type to parse into is normally inferred,
+/// but may also be given explicitly as the first argument.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::env;
+/// use cerm::require_env_parse;
+///
+/// env::set_var("PORT", "8080");
+/// let port: u16 = require_env_parse!("PORT");
+/// assert_eq!(port, 8080);
+///
+/// env::set_var("PORT", "http");
+/// let res = cerm::catch_fatal(|| require_env_parse!(u16, "PORT"));
+/// assert_eq!(
+/// 	res.unwrap_err().message,
+/// 	"invalid value “http” for environment variable PORT: invalid digit found in string",
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_env_parse {
+	($var:expr) => {
+		$crate::__require_env_parse(&$var)
+	};
+	($t:ty, $var:expr) => {
+		$crate::__require_env_parse::<$t>(&$var)
+	};
+}
+
+/// The same thing as [`require!`], but the error is passed to a closure first.
+///
+/// This macro takes a [`std::result::Result`] and a closure.  In the case of
+/// `Err(e)`, the closure is called with a reference to the error — for example
+/// to record a metric or log structured data — and then [`err!`] is called
+/// with the format string `"{e}"` as with [`require!`].  The closure must
+/// return `()`; it can’t prevent the program from exiting.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Count failures before exiting.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use cerm::require_tap;
+///
+/// static FAILURES: AtomicUsize = AtomicUsize::new(0);
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_tap!("x".parse::<i32>(), |_| {
+/// 		FAILURES.fetch_add(1, Ordering::Relaxed);
+/// 	})
+/// });
+/// assert!(res.is_err());
+/// assert_eq!(FAILURES.load(Ordering::Relaxed), 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_tap {
+	($e:expr, $f:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => {
+				let () = ($f)(&e);
+				$crate::err_code!($crate::__exit_code!(e), "{e}");
+			},
+		}
+	};
+}
+
+/// The same thing as [`require!`], but interrupted I/O exits quietly.
+///
+/// This macro takes an [`std::io::Result`].  If the error is of the kind
+/// [`ErrorKind::Interrupted`] — as happens when a system call is interrupted
+/// by a signal such as the one sent by `Ctrl-C` — the program exits with code
+/// 130 without printing a diagnostic, following the shell convention of
+/// exiting with 128 plus the number of `SIGINT`.  All other errors are handled
+/// as with [`require!`].
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::io::{self, ErrorKind};
+/// use cerm::require_signal_aware;
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_signal_aware!(Err::<(), _>(io::Error::from(ErrorKind::Interrupted)))
+/// });
+/// let fe = res.unwrap_err();
+/// assert_eq!(fe.code, 130);
+/// assert_eq!(fe.message, "");
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_signal_aware!(Err::<(), _>(io::Error::from(ErrorKind::NotFound)))
+/// });
+/// assert_eq!(res.unwrap_err().code, 1);
+/// # }
+/// ```
+///
+/// [`ErrorKind::Interrupted`]: std::io::ErrorKind::Interrupted
+#[macro_export]
+macro_rules! require_signal_aware {
+	($e:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => $crate::__exit(130),
+			Err(e) => { $crate::err_code!($crate::__exit_code!(e), "{e}"); },
+		}
+	};
+}
+
+/// Print a warning if a [`Result`] is an error, and carry on.
+///
+/// This macro takes an expression of type [`Result`] and, optionally, a format
+/// string and arguments describing what was being attempted.  If the result is
+/// an error, a warning is printed with [`warn!`] in the form `context: error`,
+/// or just `error` when no context is given.  The program never exits, and the
+/// macro evaluates to `()` whether the result is an error or not.
+///
+/// This captures the common “best effort” pattern of trying something that is
+/// allowed to fail, such as removing a temporary file.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs;
+/// use cerm::warn_if_err;
+///
+/// let p = std::path::Path::new("out.tmp");
+/// warn_if_err!(fs::remove_file(p), "couldn’t remove {}", p.display());
+/// ```
+///
+/// Nothing is printed when the result is [`Ok`].
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_if_err;
+///
+/// warn_if_err!(Ok::<(), &str>(()), "cleaning up");
+/// assert!(buf.borrow().is_empty());
+///
+/// warn_if_err!(Err::<(), _>("permission denied"), "cleaning up");
+/// assert_eq!(&*buf.borrow(), b"prog: cleaning up: permission denied\n");
+/// # }
+/// ```
+///
+/// A colon ending the context is not doubled.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_if_err;
+///
+/// for ctx in ["loading config", "loading config:", "loading config: "] {
+/// 	warn_if_err!(Err::<(), _>("no such file"), "{ctx}");
+/// 	assert_eq!(buf.take(), b"prog: loading config: no such file\n");
+/// }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_if_err {
+	($e:expr) => {
+		if let Err(e) = $e {
+			$crate::warn!("{e}");
+		}
+	};
+	($e:expr, $($fmt:tt)+) => {
+		if let Err(e) = $e {
+			$crate::warn!("{}: {e}", $crate::__Context(format_args!($($fmt)+)));
+		}
+	};
+}
+
+/// Require that every item of an iterator of [`Result`]s is [`Result::Ok`].
+///
+/// This macro takes anything implementing [`IntoIterator`] over
+/// [`std::result::Result`]s and a closure.  The items are consumed in order
+/// and, if they are all [`Result::Ok`], the macro evaluates to a [`Vec`] of
+/// their values.  On the first [`Result::Err`], the closure is called with the
+/// index of the failing item and its error, and [`err!`] is called with the
+/// value it returns, which may be anything implementing
+/// [`Display`](std::fmt::Display).  No further items are consumed.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_each;
+///
+/// let nums = require_each!(["1", "2", "3"].map(str::parse::<i32>), |i, e| {
+/// 	format!("item {i}: {e}")
+/// });
+/// assert_eq!(nums, [1, 2, 3]);
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	let args = ["1", "2", "x", "4"];
+/// 	require_each!(args.map(str::parse::<i32>), |i, e| format!("item {i}: {e}"))
+/// });
+/// assert_eq!(res.unwrap_err().message, "item 2: invalid digit found in string");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_each {
+	($e:expr, $f:expr) => {{
+		let mut vs = ::std::vec::Vec::new();
+		for (i, r) in ::core::iter::IntoIterator::into_iter($e).enumerate() {
+			match r {
+				Ok(v) => vs.push(v),
+				Err(e) => { $crate::err!("{}", ($f)(i, e)); },
+			}
+		}
+		vs
+	}};
+}
+
+/// Print a diagnostic whose severity is only known at runtime.
+///
+/// The first argument is a [`Level`] and the remaining arguments are the same
+/// as those of [`warn!`].  With [`Level::Warning`] the diagnostic is printed as
+/// with [`warn!`] and the program carries on, while with [`Level::Error`] it is
+/// printed as with [`err!`] and the program exits with code `1`.  Use
+/// [`diag_code!`] to exit with a different code.
+///
+/// # Panics
+///
+/// Calls [`err!`] or [`warn!`] which may panic if they fail.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::{diag, Level};
+///
+/// let strict = false;
+/// let level = if strict { Level::Error } else { Level::Warning };
+/// diag!(level, "unknown option “{}” ignored", "colour");
+///
+/// let res = cerm::catch_fatal(|| diag!(Level::Warning, "not fatal"));
+/// assert!(res.is_ok());
+///
+/// let res = cerm::catch_fatal(|| diag!(Level::Error, "fatal"));
+/// assert_eq!(res.unwrap_err().code, 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! diag {
+	($level:expr, $($fmt:tt)+) => {
+		$crate::diag_fmt($level, 1, format_args!($($fmt)+))
+	};
+}
+
+/// The same thing as [`diag!`], but with an exit code.
+///
+/// The exit code is the second argument and is used only when the level is
+/// [`Level::Error`], in which case the diagnostic is printed as with
+/// [`err_code!`].
+///
+/// # Panics
+///
+/// Calls [`err_code!`] or [`warn!`] which may panic if they fail.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::{diag_code, Level};
+///
+/// let res = cerm::catch_fatal(|| diag_code!(Level::Warning, 65, "bad input"));
+/// assert!(res.is_ok());
+///
+/// let res = cerm::catch_fatal(|| diag_code!(Level::Error, 65, "bad input"));
+/// assert_eq!(res.unwrap_err().code, 65);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! diag_code {
+	($level:expr, $code:expr, $($fmt:tt)+) => {
+		$crate::diag_fmt($level, $code, format_args!($($fmt)+))
+	};
+}
+
+/// Require that a collection is not empty.
+///
+/// This macro takes an expression with an `is_empty()` method, such as a
+/// slice, a [`Vec`] or a [`HashMap`](std::collections::HashMap), followed by
+/// the same arguments you would pass to [`err!`].  If the collection is empty,
+/// [`err!`] is called with the given arguments.  Otherwise the macro evaluates
+/// to the collection itself.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// Exit like `grep` when nothing was found.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_nonempty;
+///
+/// let pattern = "foo";
+/// let lines = ["foo", "bar", "foobar"];
+/// let found: Vec<_> = lines.iter().filter(|l| l.contains(pattern)).collect();
+/// let found = require_nonempty!(found, "no matches for “{pattern}”");
+/// assert_eq!(found.len(), 2);
+///
+/// let res = cerm::catch_fatal(|| require_nonempty!(Vec::<()>::new(), "no matches"));
+/// assert_eq!(res.unwrap_err().message, "no matches");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_nonempty {
+	($e:expr, $($fmt:tt)+) => {
+		match $e {
+			v if v.is_empty() => { $crate::err!($($fmt)+); },
+			v => v,
+		}
+	};
+}
+
+/// Print an error built from a registered template and exit.
+///
+/// The first argument is the key of a template registered with
+/// [`register_template()`], and the remaining arguments are the fields to
+/// substitute into it, given as `name = value` pairs where each value
+/// implements [`Display`](std::fmt::Display).  The expanded template is then
+/// printed as with [`err!`].  If no template is registered under the key, the
+/// error printed says so instead.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::err_template;
+///
+/// cerm::register_template("no-input", "cannot read {path}: {reason}");
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	err_template!("no-input", path = "in.txt", reason = "no such file");
+/// });
+/// assert_eq!(res.unwrap_err().message, "cannot read in.txt: no such file");
+///
+/// let res = cerm::catch_fatal(|| { err_template!("no-output"); });
+/// assert_eq!(res.unwrap_err().message, "unknown diagnostic template “no-output”");
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! err_template {
+	($key:expr $(, $name:ident = $val:expr)* $(,)?) => {
+		$crate::__err_template(
+			&$key,
+			&[$((::core::stringify!($name), &$val as &dyn ::core::fmt::Display)),*],
+		)
+	};
+}
+
+/// Print a warning built from a registered template.
+///
+/// This macro takes the same arguments as [`err_template!`], but the expanded
+/// template is printed as with [`warn!`] and the program carries on.  If no
+/// template is registered under the key, the warning printed says so instead.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::warn_template;
+///
+/// cerm::register_template("skipped", "skipping {path}: {{binary}}");
+/// warn_template!("skipped", path = "a.out");
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! warn_template {
+	($key:expr $(, $name:ident = $val:expr)* $(,)?) => {
+		$crate::__warn_template(
+			&$key,
+			&[$((::core::stringify!($name), &$val as &dyn ::core::fmt::Display)),*],
+		)
+	};
+}
+
+/// Run a block without letting fatal diagnostics exit the program.
+///
+/// This is a shorthand for calling [`catch_fatal()`] with a closure whose body
+/// is the given block, so the macro evaluates to a
+/// [`Result`]`<T, `[`FatalExit`]`>` where `T` is the type of the block.  Only
+/// the current thread is affected, and the [`exit_behavior()`] is left alone.
+/// As the block is the body of a closure, `return` and `?` inside it leave the
+/// block and not the enclosing function.
+///
+/// # Examples
+///
+/// ```
+/// use cerm::{err_code, with_no_exit, FatalExit};
+///
+/// let res = with_no_exit!({
+/// 	err_code!(7, "lost connection");
+/// });
+/// assert!(matches!(res, Err(FatalExit { code: 7, .. })));
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! with_no_exit {
+	($body:block) => {
+		$crate::catch_fatal(|| $body)
+	};
+}
+
+/// The same thing as [`require_retry!`], but only some errors are retried.
+///
+/// This macro takes the number of attempts, an optional [`Backoff`] or
+/// [`Duration`](std::time::Duration), a closure returning a
+/// [`std::result::Result`], and a predicate taking a reference to an error.  An
+/// attempt is retried only if the predicate returns `true` for its error, so
+/// that permanent failures are reported immediately.  When no backoff is
+/// given, attempts are made without waiting in between.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::io::{self, ErrorKind};
+/// use cerm::require_retry_if;
+///
+/// let would_block = |e: &io::Error| e.kind() == ErrorKind::WouldBlock;
+///
+/// let mut tries = 0;
+/// let n = require_retry_if!(3, || {
+/// 	tries += 1;
+/// 	match tries {
+/// 		1 | 2 => Err(io::Error::from(ErrorKind::WouldBlock)),
+/// 		_ => Ok(tries),
+/// 	}
+/// }, would_block);
+/// assert_eq!(n, 3);
+///
+/// let mut tries = 0;
+/// let res = cerm::catch_fatal(|| {
+/// 	require_retry_if!(3, || {
+/// 		tries += 1;
+/// 		Err::<(), _>(io::Error::from(ErrorKind::PermissionDenied))
+/// 	}, would_block)
+/// });
+/// assert_eq!(res.unwrap_err().message, "permission denied");
+/// assert_eq!(tries, 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_retry_if {
+	($n:expr, $f:expr, $pred:expr) => {
+		$crate::require_retry_if!($n, ::std::time::Duration::ZERO, $f, $pred)
+	};
+	($n:expr, $backoff:expr, $f:expr, $pred:expr) => {
+		$crate::require!($crate::__retry_if(
+			$n,
+			::core::convert::Into::into($backoff),
+			$f,
+			$pred,
+		))
+	};
+}
+
+/// Keep the diagnostics printed by a block together.
+///
+/// The diagnostics printed by the current thread while the block runs are held
+/// back, and written out with a single write once the block is left, whether
+/// normally, by panicking, or by exiting with [`err!`].  They are therefore
+/// printed contiguously, and are never interleaved with the diagnostics of
+/// other threads or processes writing to the same place.  This is useful for
+/// an error followed by several lines giving more details.  Nested groups are
+/// written out by the outermost group.  The macro evaluates to the value of
+/// the block.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{warn, with_grouped_output};
+///
+/// let files = ["a.txt", "b.txt"];
+/// with_grouped_output!({
+/// 	warn!("{} files could not be read", files.len());
+/// 	for f in files {
+/// 		warn!("  {f}");
+/// 	}
+/// 	assert!(buf.borrow().is_empty());
+/// });
+/// assert_eq!(
+/// 	buf.take(),
+/// 	b"prog: 2 files could not be read\nprog:   a.txt\nprog:   b.txt\n",
+/// );
+/// ```
+///
+/// Groups printed by different threads never interleave.
+///
+/// ```
+/// use std::io::{self, Write};
+/// use std::sync::{Arc, Mutex};
+/// use std::thread;
+/// use cerm::{warn, with_grouped_output};
+///
+/// #[derive(Clone)]
+/// struct Shared(Arc<Mutex<Vec<u8>>>);
+///
+/// impl Write for Shared {
+/// 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+/// 		self.0.lock().unwrap().write(buf)
+/// 	}
+///
+/// 	fn flush(&mut self) -> io::Result<()> {
+/// 		Ok(())
+/// 	}
+/// }
+///
+/// cerm::set_progname("prog");
+/// let out = Shared(Arc::new(Mutex::new(Vec::new())));
+/// let workers: Vec<_> = (0..4)
+/// 	.map(|id| {
+/// 		let out = out.clone();
+/// 		thread::spawn(move || {
+/// 			cerm::set_local_sink(out);
+/// 			for _ in 0..100 {
+/// 				with_grouped_output!({
+/// 					warn!("worker {id} failed");
+/// 					thread::yield_now();
+/// 					warn!("  note: worker {id}");
+/// 					thread::yield_now();
+/// 					warn!("  note: worker {id}");
+/// 				});
+/// 			}
+/// 		})
+/// 	})
+/// 	.collect();
+/// for w in workers {
+/// 	w.join().unwrap();
+/// }
+///
+/// let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+/// let lines: Vec<_> = out.lines().collect();
+/// assert_eq!(lines.len(), 4 * 100 * 3);
+/// for group in lines.chunks(3) {
+/// 	let id = group[0].strip_prefix("prog: worker ").unwrap();
+/// 	let id = id.strip_suffix(" failed").unwrap();
+/// 	assert_eq!(group[1], format!("prog:   note: worker {id}"));
+/// 	assert_eq!(group[2], format!("prog:   note: worker {id}"));
+/// }
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! with_grouped_output {
+	($body:block) => {{
+		let _group = $crate::__OutputGroup::begin();
+		$body
+	}};
+}
+
+/// The same thing as [`require!`], but the diagnostic says how long the
+/// operation took.
+///
+/// This macro takes a closure returning a [`std::result::Result`] and,
+/// optionally, a format string and arguments describing the operation.  The
+/// closure is called and timed.  If it returns [`Result::Ok`], the value is
+/// returned and the time is discarded.  Otherwise [`err!`] is called with the
+/// message `context: error (after time)`, or just `error (after time)` when no
+/// context is given.
+///
+/// This is useful for slow operations that eventually fail, such as those
+/// giving up after a timeout.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::thread;
+/// use std::time::Duration;
+/// use cerm::require_timed;
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	require_timed!(|| {
+/// 		thread::sleep(Duration::from_millis(10));
+/// 		Err::<(), _>("connection timed out")
+/// 	}, "connecting to {}", "example.org")
+/// });
+/// let msg = res.unwrap_err().message;
+/// assert!(msg.starts_with("connecting to example.org: connection timed out (after "));
+/// assert!(msg.ends_with("ms)"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_timed {
+	($f:expr) => {{
+		let start = ::std::time::Instant::now();
+		match ($f)() {
+			Ok(v) => v,
+			Err(e) => {
+				$crate::err_code!($crate::__exit_code!(e), "{e} (after {:.2?})", start.elapsed());
+			},
+		}
+	}};
+	($f:expr, $($fmt:tt)+) => {{
+		let start = ::std::time::Instant::now();
+		match ($f)() {
+			Ok(v) => v,
+			Err(e) => {
+				$crate::err_code!(
+					$crate::__exit_code!(e),
+					"{}: {e} (after {:.2?})",
+					$crate::__Context(format_args!($($fmt)+)),
+					start.elapsed(),
+				);
+			},
+		}
+	}};
+}
+
+/// Print an error made of a summary and details, and exit with a given code.
+///
+/// The first argument is the exit code and the second is a one-line summary
+/// implementing [`Display`](std::fmt::Display), which is not treated as a
+/// format string.  The remaining arguments are passed to the [`format_args!`]
+/// macro to build the details, which may span multiple lines.  The summary is
+/// printed as with [`err_code!`], and every line of the details is printed
+/// beneath it indented by two spaces.
+///
+/// # Panics
+///
+/// Calls [`err_code!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::err_detail;
+///
+/// let res = cerm::catch_fatal(|| {
+/// 	err_detail!(2, "config.toml: invalid key “colour”", "did you mean “color”?");
+/// });
+/// assert_eq!(
+/// 	res.unwrap_err().message,
+/// 	"config.toml: invalid key “colour”\n  did you mean “color”?",
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! err_detail {
+	($code:expr, $summary:expr, $($fmt:tt)+) => {
+		$crate::err_code!(
+			$code,
+			"{}{}",
+			$summary,
+			$crate::__Detail(format_args!($($fmt)+)),
+		);
+	};
+}
+
+/// Print a warning made of a summary and details.
+///
+/// The first argument is a one-line summary implementing
+/// [`Display`](std::fmt::Display), and the remaining arguments are the details
+/// as with [`err_detail!`].  The summary is printed as with [`warn!`], and
+/// every line of the details is printed beneath it indented by two spaces.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_detail;
+///
+/// let new = "--color";
+/// warn_detail!("--colour is deprecated", "use {new} instead\nsee the manual");
+/// assert_eq!(
+/// 	buf.take(),
+/// 	b"prog: --colour is deprecated\n  use --color instead\n  see the manual\n",
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_detail {
+	($summary:expr, $($fmt:tt)+) => {
+		$crate::warn!("{}{}", $summary, $crate::__Detail(format_args!($($fmt)+)));
+	};
+}
+
+/// Require that a value satisfies a predicate.
+///
+/// This macro takes a value, a closure taking a reference to the value and
+/// returning a [`bool`], and the same arguments you would pass to [`err!`].
+/// If the closure returns `true` the macro evaluates to the value, and
+/// otherwise [`err!`] is called with the given arguments.  When the value is a
+/// variable, the message can refer to it as any other variable in scope.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_that;
+///
+/// let port = 8080;
+/// let port = require_that!(port, |p| (1..=65535).contains(p), "port {port} out of range");
+/// assert_eq!(port, 8080);
+///
+/// let port = 0;
+/// let res = cerm::catch_fatal(|| {
+/// 	require_that!(port, |p| (1..=65535).contains(p), "port {port} out of range")
+/// });
+/// assert_eq!(res.unwrap_err().message, "port 0 out of range");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_that {
+	($v:expr, $pred:expr, $($fmt:tt)+) => {
+		match $v {
+			v if ($pred)(&v) => v,
+			_ => { $crate::err!($($fmt)+); },
+		}
+	};
+}
+
+/// The same thing as [`warn!`], but the message is about a possibly non-UTF-8
+/// name.
+///
+/// The first argument is anything implementing [`ToLossy`], such as a
+/// [`Path`](std::path::Path), an [`OsStr`](std::ffi::OsStr), or a byte
+/// string.  It is printed in quotes between the program name and the message,
+/// as in `"progname: “name”: message"`, with any invalid UTF-8 replaced as
+/// described in [`ToLossy`].  The remaining arguments are the same as those of
+/// [`warn!`].
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// # #[cfg(unix)] {
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use cerm::warn_path;
+///
+/// let name = OsStr::from_bytes(b"caf\xe9.txt");
+/// warn_path!(name, "skipping empty file");
+/// assert_eq!(
+/// 	String::from_utf8(buf.take()).unwrap(),
+/// 	"prog: “caf\u{fffd}.txt”: skipping empty file\n",
+/// );
+/// # }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_path {
+	($path:expr, $($fmt:tt)+) => {
+		$crate::warn!(
+			"“{}”: {}",
+			$crate::ToLossy::to_lossy(&$path),
+			format_args!($($fmt)+),
+		);
+	};
+}
+
+/// The same thing as [`err!`], but the message is about a possibly non-UTF-8
+/// name.
+///
+/// The arguments are the same as those of [`warn_path!`], and the diagnostic
+/// is printed in the same form before exiting as with [`err!`].
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::env;
+/// use std::fs::File;
+/// use cerm::err_path;
+///
+/// for name in env::args_os().skip(1) {
+/// 	if let Err(e) = File::open(&name) {
+/// 		err_path!(name, "{e}");
+/// 	}
+/// }
+/// ```
+#[macro_export]
+macro_rules! err_path {
+	($path:expr, $($fmt:tt)+) => {
+		$crate::err!(
+			"“{}”: {}",
+			$crate::ToLossy::to_lossy(&$path),
+			format_args!($($fmt)+),
+		);
+	};
+}
+
+/// Types that functions can return to stop with an error.
+///
+/// This trait is used by [`require_or_return!`] to decide what to do with an
+/// error based on the return type of the function it is used in.  It is
+/// implemented for `()`, in which case the error is printed and the program
+/// exits as with [`err!`], and for every [`std::result::Result`] whose error
+/// type can be converted from the error, in which case the error is returned
+/// as with the `?` operator.
+pub trait FatalOrReturn<E> {
+	/// Print `e` and exit, or convert it into a value to return.
+	fn fatal_or_return(e: E) -> Self;
+}
+
+impl<E: fmt::Display> FatalOrReturn<E> for () {
+	fn fatal_or_return(e: E) -> Self {
+		err_fmt(1, format_args!("{e}"));
+	}
+}
+
+impl<T, E, F: From<E>> FatalOrReturn<E> for Result<T, F> {
+	fn fatal_or_return(e: E) -> Self {
+		Err(e.into())
+	}
+}
+
+/// Require that an expression returns [`Result::Ok`], or stop the function.
+///
+/// This macro takes a [`std::result::Result`] and in the case of `Ok(v)`
+/// returns `v`.  What happens in the case of an error depends on the return
+/// type of the enclosing function, as described in [`FatalOrReturn`].  In a
+/// function returning a [`std::result::Result`], the error is returned as with
+/// the `?` operator.  In a function returning `()`, such as `main`, the error
+/// is printed and the program exits as with [`require!`].  This gives the same
+/// way of stopping with an error across binary and library code.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// In a function returning a [`std::result::Result`], the error is returned.
+///
+/// ```
+/// use std::num::ParseIntError;
+/// use cerm::require_or_return;
+///
+/// fn parse(s: &str) -> Result<i32, ParseIntError> {
+/// 	let n = require_or_return!(s.parse::<i32>());
+/// 	Ok(n * 2)
+/// }
+///
+/// assert_eq!(parse("21"), Ok(42));
+/// assert!(parse("x").is_err());
+/// ```
+///
+/// In a function returning `()`, the program exits.
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_or_return;
+///
+/// fn run(s: &str) {
+/// 	let n = require_or_return!(s.parse::<i32>());
+/// 	println!("{n}");
+/// }
+///
+/// let res = cerm::catch_fatal(|| run("x"));
+/// assert_eq!(res.unwrap_err().message, "invalid digit found in string");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_or_return {
+	($e:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => return $crate::FatalOrReturn::fatal_or_return(e),
+		}
+	};
+}
+
+/// The same thing as [`require!`], but for nested [`Result`]s.
+///
+/// This macro takes a `Result<Result<T, E2>, E1>`, such as is returned by an
+/// operation made of two fallible steps like parsing and then validating, and,
+/// optionally, a format string and arguments describing the operation.  If both layers are
+/// [`Result::Ok`], the macro evaluates to the inner value.  Otherwise [`err!`]
+/// is called with the error of whichever layer failed, in the form
+/// `context: error`, or just `error` when no context is given.  The context is
+/// the same for both layers, so it should describe the operation as a whole.
+/// Both error types must implement [`Display`](std::fmt::Display).
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::require_flat;
+///
+/// let ok: Result<Result<i32, &str>, &str> = Ok(Ok(42));
+/// assert_eq!(require_flat!(ok, "computing"), 42);
+///
+/// let outer: Result<Result<i32, &str>, &str> = Err("spawn failed");
+/// let res = cerm::catch_fatal(|| require_flat!(outer, "computing"));
+/// assert_eq!(res.unwrap_err().message, "computing: spawn failed");
+///
+/// let inner: Result<Result<i32, &str>, &str> = Ok(Err("overflow"));
+/// let res = cerm::catch_fatal(|| require_flat!(inner));
+/// assert_eq!(res.unwrap_err().message, "overflow");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_flat {
+	($e:expr) => {
+		match $e {
+			Ok(Ok(v)) => v,
+			Ok(Err(e)) => { $crate::err_code!($crate::__exit_code!(e), "{e}"); },
+			Err(e) => { $crate::err_code!($crate::__exit_code!(e), "{e}"); },
+		}
+	};
+	($e:expr, $($fmt:tt)+) => {
+		match $e {
+			Ok(Ok(v)) => v,
+			Ok(Err(e)) => {
+				$crate::err_code!(
+					$crate::__exit_code!(e),
+					"{}: {e}",
+					$crate::__Context(format_args!($($fmt)+)),
+				);
+			},
+			Err(e) => {
+				$crate::err_code!(
+					$crate::__exit_code!(e),
+					"{}: {e}",
+					$crate::__Context(format_args!($($fmt)+)),
+				);
+			},
+		}
+	};
+}
+
+/// The same thing as [`warn!`], but without the program name.
+///
+/// The message is printed exactly as given, followed by the line terminator,
+/// but is otherwise handled like any other warning.  This is useful when
+/// relaying diagnostics which already carry a prefix, such as those of a child
+/// process.
+///
+/// # Panics
+///
+/// Calls [`eprint!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_raw;
+///
+/// let line = "cc: warning: unused variable";
+/// warn_raw!("{line}");
+/// assert_eq!(buf.take(), b"cc: warning: unused variable\n");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_raw {
+	($($fmt:tt)+) => {
+		$crate::__warn_raw(format_args!($($fmt)+));
+	};
+}
+
+/// The same thing as [`err_code!`], but without the program name.
+///
+/// The message is printed exactly as given, followed by the line terminator,
+/// and the program then exits with the given code as with [`err_code!`].
+///
+/// # Panics
+///
+/// Calls [`eprint!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::err_raw;
+///
+/// let res = cerm::catch_fatal(|| { err_raw!(2, "cc: error: no input files"); });
+/// assert_eq!(res.unwrap_err().code, 2);
+/// assert_eq!(buf.take(), b"cc: error: no input files\n");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! err_raw {
+	($code:expr, $($fmt:tt)+) => {
+		$crate::__err_raw($code, format_args!($($fmt)+));
+	};
+}
+
+/// Print a warning, or exit once the warning has been printed too often.
+///
+/// The first argument is the number of times the warning may be printed, the
+/// second is an exit code, and the remaining arguments are the same as those
+/// of [`warn!`].  Each call site keeps its own count.  The first `threshold`
+/// times a call site is reached the diagnostic is printed as with [`warn!`],
+/// and the next time it is printed as with [`err_code!`] with the given code,
+/// exiting the program.  This is useful for conditions which are tolerable
+/// when they happen a few times, but not when they keep happening.
+///
+/// # Panics
+///
+/// Calls [`warn!`] or [`err_code!`] which may panic if they fail.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use cerm::warn_escalate;
+///
+/// let mut warnings = 0;
+/// let res = cerm::catch_fatal(|| {
+/// 	for _ in 0..10 {
+/// 		warn_escalate!(3, 75, "connection reset, reconnecting");
+/// 		warnings += 1;
+/// 	}
+/// });
+/// assert_eq!(warnings, 3);
+/// assert_eq!(res.unwrap_err().code, 75);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! warn_escalate {
+	($threshold:expr, $code:expr, $($fmt:tt)+) => {{
+		static COUNT: ::core::sync::atomic::AtomicUsize =
+			::core::sync::atomic::AtomicUsize::new(0);
+		if COUNT.fetch_add(1, ::core::sync::atomic::Ordering::Relaxed) < $threshold {
+			$crate::warn!($($fmt)+);
+		} else {
+			$crate::err_code!($code, $($fmt)+);
+		}
+	}};
+}
+
+/// Push a breadcrumb onto the context stack until the end of the scope.
+///
+/// This macro takes the same arguments as [`format!`], pushes the formatted
+/// string onto the context stack of the current thread as with
+/// [`push_context()`], and returns a [`ContextGuard`] which pops it again when
+/// dropped.  The guard must be bound to a variable to stay alive until the end
+/// of the scope.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("mytool");
+/// use cerm::{context, warn};
+///
+/// fn parse_section(name: &str) {
+/// 	let _ctx = context!("[{name}]");
+/// 	warn!("port: invalid value");
+/// }
+///
+/// let _ctx = context!("config.toml");
+/// parse_section("server");
+/// warn!("done");
+/// assert_eq!(
+/// 	buf.take(),
+/// 	b"mytool: config.toml: [server]: port: invalid value\nmytool: config.toml: done\n",
+/// );
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! context {
+	($($fmt:tt)+) => {
+		$crate::ContextGuard::__new(::std::format!($($fmt)+))
+	};
+}
+
+/// Errors which can tell whether they represent a timeout.
+///
+/// This trait is used by [`require_deadline!`] to choose the exit code of an
+/// error.  It is implemented for [`std::io::Error`], which is a timeout when
+/// its kind is [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut), and for
+/// [`RecvTimeoutError`](std::sync::mpsc::RecvTimeoutError), which is a timeout
+/// when it is `RecvTimeoutError::Timeout`.  Implement it for your own error
+/// types to use them with [`require_deadline!`].
+pub trait Timeout {
+	/// Return whether the error represents a timeout.
+	fn is_timeout(&self) -> bool;
+}
+
+impl Timeout for std::io::Error {
+	fn is_timeout(&self) -> bool {
+		self.kind() == std::io::ErrorKind::TimedOut
+	}
+}
+
+impl Timeout for std::sync::mpsc::RecvTimeoutError {
+	fn is_timeout(&self) -> bool {
+		*self == Self::Timeout
+	}
+}
+
+impl<T: Timeout + ?Sized> Timeout for &T {
+	fn is_timeout(&self) -> bool {
+		(**self).is_timeout()
+	}
+}
+
+/// The same thing as [`require!`], but timeouts exit with code 124.
+///
+/// This macro takes a [`std::result::Result`] whose error type implements
+/// [`Timeout`] and, optionally, a format string and arguments describing the
+/// operation.  If the error represents a timeout, the error is printed and the
+/// program exits with code 124, following the convention of GNU `timeout(1)`.
+/// All other errors are handled as with [`require!`].  With a context, the
+/// error is printed in the form `context: error`.
+///
+/// # Panics
+///
+/// Calls [`err_code!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+/// use cerm::require_deadline;
+///
+/// let (tx, rx) = mpsc::channel::<()>();
+/// let res = cerm::catch_fatal(|| {
+/// 	require_deadline!(rx.recv_timeout(Duration::from_millis(1)), "waiting for a worker")
+/// });
+/// let fe = res.unwrap_err();
+/// assert_eq!(fe.code, 124);
+/// assert_eq!(fe.message, "waiting for a worker: timed out waiting on channel");
+///
+/// drop(tx);
+/// let res = cerm::catch_fatal(|| require_deadline!(rx.recv_timeout(Duration::MAX)));
+/// assert_eq!(res.unwrap_err().code, 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_deadline {
+	($e:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => {
+				let code = if $crate::Timeout::is_timeout(&e) { 124 } else { $crate::__exit_code!(e) };
+				$crate::err_code!(code, "{e}");
+			},
+		}
+	};
+	($e:expr, $($fmt:tt)+) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => {
+				let code = if $crate::Timeout::is_timeout(&e) { 124 } else { $crate::__exit_code!(e) };
+				$crate::err_code!(
+					code,
+					"{}: {e}",
+					$crate::__Context(format_args!($($fmt)+)),
+				);
+			},
+		}
+	};
+}
+
+/// The same thing as [`require!`], but the expression is a future.
+///
+/// This macro awaits the given future, and then handles its output exactly as
+/// [`require!`] would, taking the same additional arguments when the output
+/// is an [`std::option::Option`].  As it uses `.await`, it can only be used in
+/// async functions and blocks.  As with all fatal diagnostics, the hook set
+/// with [`set_async_flush_hook()`] is run before exiting.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # use std::future::Future;
+/// # use std::pin::pin;
+/// # use std::sync::Arc;
+/// # use std::task::{Context, Poll, Wake};
+/// # struct Noop;
+/// # impl Wake for Noop {
+/// # 	fn wake(self: Arc<Self>) {}
+/// # }
+/// # fn block_on<F: Future>(f: F) -> F::Output {
+/// # 	let waker = Arc::new(Noop).into();
+/// # 	let mut cx = Context::from_waker(&waker);
+/// # 	let mut f = pin!(f);
+/// # 	loop {
+/// # 		if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+/// # 			return v;
+/// # 		}
+/// # 	}
+/// # }
+/// use cerm::require_await;
+///
+/// async fn fetch(ok: bool) -> Result<&'static str, &'static str> {
+/// 	if ok { Ok("data") } else { Err("connection refused") }
+/// }
+///
+/// block_on(async {
+/// 	assert_eq!(require_await!(fetch(true)), "data");
+/// 	assert_eq!(require_await!(async { Some(42) }, "no answer"), 42);
+/// });
+///
+/// let res = cerm::catch_fatal(|| block_on(async { require_await!(fetch(false)) }));
+/// assert_eq!(res.unwrap_err().message, "connection refused");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_await {
+	($e:expr) => {
+		$crate::require!($e.await)
+	};
+	($e:expr, $($fmt:tt)+) => {
+		$crate::require!($e.await, $($fmt)+)
+	};
+}
+
+/// Require that an [`std::option::Option`] is [`Option::Some`], warning and
+/// falling back to a default otherwise.
+///
+/// This macro takes an [`std::option::Option`], a default value, and the same
+/// arguments you would pass to [`warn!`].  If the option is [`Option::Some`],
+/// the macro evaluates to the contained value.  Otherwise [`warn!`] is called
+/// with the given arguments and the macro evaluates to the default, which is
+/// only evaluated in that case.
+///
+/// This is useful for things that are missing but can be recovered from, where
+/// the user should still be told that a fallback is being used.
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::require_or_warn;
+///
+/// let width = require_or_warn!(Some(120), unreachable!(), "width unset");
+/// assert_eq!(width, 120);
+/// assert!(buf.borrow().is_empty());
+///
+/// let width = require_or_warn!(None, 80, "width unset, using {}", 80);
+/// assert_eq!(width, 80);
+/// assert_eq!(buf.take(), b"prog: width unset, using 80\n");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_or_warn {
+	($e:expr, $default:expr, $($fmt:tt)+) => {
+		match $e {
+			Some(v) => v,
+			None => {
+				$crate::warn!($($fmt)+);
+				$default
+			},
+		}
+	};
+}
+
+/// Errors which know the exit code they should cause.
+///
+/// Implement this trait for your own error types to put the choice of exit
+/// code on the error itself, and then report them with [`require_coded!`].
+/// Unlike the mapper set with [`set_exit_code_from_error()`], which applies to
+/// every [`FatalError`] passed to [`require!`], this works with the error
+/// types themselves.
+pub trait ExitCoded {
+	/// Return the exit code the program should exit with when reporting this
+	/// error.
+	fn exit_code(&self) -> i32;
+}
+
+impl<T: ExitCoded + ?Sized> ExitCoded for &T {
+	fn exit_code(&self) -> i32 {
+		(**self).exit_code()
+	}
+}
+
+/* The exit code for an error given to require_coded!(), using the same trick
+   as for require!() to fall back to 1 for errors not implementing ExitCoded */
+
+#[doc(hidden)]
+pub trait __ExitCoded {
+	fn __exit_coded(&self) -> i32;
+}
+
+impl<E: ExitCoded> __ExitCoded for E {
+	fn __exit_coded(&self) -> i32 {
+		self.exit_code()
+	}
+}
+
+#[doc(hidden)]
+pub trait __ExitCodedFallback {
+	fn __exit_coded(&self) -> i32;
+}
+
+impl<E: fmt::Display> __ExitCodedFallback for &E {
+	fn __exit_coded(&self) -> i32 {
+		1
+	}
+}
+
+/// The same thing as [`require!`], but the exit code is chosen by the error.
+///
+/// This macro takes a [`std::result::Result`].  In the case of `Ok(v)` it
+/// returns `v`, and in the case of `Err(e)` it calls [`err_code!`] with the
+/// format string `"{e}"` and the code returned by [`ExitCoded::exit_code()`].
+/// Errors which don’t implement [`ExitCoded`] exit with 1.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::fmt;
+/// use cerm::{require_coded, ExitCoded};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+/// 	Usage,
+/// 	NoInput(&'static str),
+/// }
+///
+/// impl fmt::Display for AppError {
+/// 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/// 		match self {
+/// 			Self::Usage => write!(f, "usage: mytool file"),
+/// 			Self::NoInput(path) => write!(f, "{path}: no such file"),
+/// 		}
+/// 	}
+/// }
+///
+/// impl ExitCoded for AppError {
+/// 	fn exit_code(&self) -> i32 {
+/// 		match self {
+/// 			Self::Usage => 64,
+/// 			Self::NoInput(_) => 66,
+/// 		}
+/// 	}
+/// }
+///
+/// assert_eq!(require_coded!(Ok::<_, AppError>(42)), 42);
+///
+/// let res = cerm::catch_fatal(|| require_coded!(Err::<(), _>(AppError::Usage)));
+/// assert_eq!(res.unwrap_err().code, 64);
+///
+/// let res = cerm::catch_fatal(|| require_coded!(Err::<(), _>(AppError::NoInput("in.txt"))));
+/// let fe = res.unwrap_err();
+/// assert_eq!((fe.code, fe.message.as_str()), (66, "in.txt: no such file"));
+///
+/// let res = cerm::catch_fatal(|| require_coded!(Err::<(), _>("not coded")));
+/// assert_eq!(res.unwrap_err().code, 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_coded {
+	($e:expr) => {
+		match $e {
+			Ok(v) => v,
+			Err(e) => {
+				#[allow(unused_imports)]
+				use $crate::{__ExitCoded as _, __ExitCodedFallback as _};
+				$crate::err_code!((&e).__exit_coded(), "{e}");
+			},
+		}
+	};
+}
+
+/// The same thing as [`warn!`], but only the first call in the whole program
+/// prints anything.
+///
+/// This macro takes the same arguments as [`warn!`].  The first time any
+/// invocation of it is reached, from any thread, the warning is printed; every
+/// later invocation prints nothing, no matter where it is invoked from or what
+/// its message is.  It is meant for notices that only make sense once per run,
+/// such as announcing that an experimental mode is enabled.  To print a warning
+/// once per key instead, use [`warn_deprecated!`].
+///
+/// # Panics
+///
+/// Calls [`warn!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::warn_global_once;
+///
+/// fn parse() {
+/// 	warn_global_once!("experimental parser enabled");
+/// }
+///
+/// fn render() {
+/// 	warn_global_once!("experimental renderer enabled");
+/// }
+///
+/// parse();
+/// render();
+/// parse();
+/// assert_eq!(buf.take(), b"prog: experimental parser enabled\n");
+/// ```
+#[cfg(feature = "full")]
+#[macro_export]
+macro_rules! warn_global_once {
+	($($fmt:tt)+) => {
+		$crate::__warn_global_once(format_args!($($fmt)+));
+	};
+}
+
+/// Exit the program with the given code without printing a diagnostic.
+///
+/// This behaves like [`err_code!`] in every way except that nothing is printed.
+/// The exit code filters, [`exit_behavior()`], and hooks all apply.
+/// Use it when the user has already been told what went wrong, rather than
+/// passing an empty message to [`err_code!`].
+///
+/// # Panics
+///
+/// Under [`ExitBehavior::Panic`], or inside [`catch_fatal()`], this panics
+/// with a [`FatalExit`] whose message is empty instead of exiting.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// # let buf = cerm::__capture();
+/// # cerm::set_progname("prog");
+/// use cerm::{err, exit_quiet};
+///
+/// let res = cerm::catch_fatal(|| { exit_quiet!(3); });
+/// assert_eq!(res.unwrap_err().code, 3);
+/// assert!(buf.take().is_empty());
+///
+/// /* An empty message is pointed out when this crate is built in debug mode */
+/// let res = cerm::catch_fatal(|| { err!(""); });
+/// assert_eq!(res.unwrap_err().code, 1);
+/// let out = buf.take();
+/// assert!(
+/// 	out == b"prog: \n"
+/// 		|| out == b"prog: fatal diagnostic has an empty message\nprog: \n"
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! exit_quiet {
+	($code:expr) => {
+		$crate::__exit($code)
+	};
+}
+
+/// Require that an operation with no result succeeds.
+///
+/// This macro is the same thing as [`require!`] for a `Result<(), E>`, but
+/// reads as a statement that the given side effect must happen, such as a
+/// write, a flush, or creating a directory.  Optionally, a format string and
+/// arguments describing the operation may be given, in which case the error is
+/// printed in the form `context: error`.  The exit code is chosen as by
+/// [`require!`].  The [`Result::Ok`] value must be `()`; use [`require!`] for
+/// operations whose result you need.
+///
+/// # Panics
+///
+/// Calls [`err!`] which may panic if it fails.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "full")] {
+/// use std::fs;
+/// use std::io::Write;
+/// use cerm::must;
+///
+/// let mut out = Vec::new();
+/// must!(writeln!(out, "hello"));
+/// must!(out.flush(), "flushing output");
+/// assert_eq!(out, b"hello\n");
+///
+/// let res = cerm::catch_fatal(|| must!(fs::create_dir("/nonexistent/dir"), "creating “/nonexistent/dir”"));
+/// assert!(res.unwrap_err().message.starts_with("creating “/nonexistent/dir”: "));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! must {
+	($e:expr) => {
+		match $e {
+			Ok(()) => (),
+			Err(e) => {
+				$crate::err_code!($crate::__exit_code!(e), "{e}");
+			},
+		}
+	};
+	($e:expr, $($fmt:tt)+) => {
+		match $e {
+			Ok(()) => (),
+			Err(e) => {
+				$crate::err_code!(
+					$crate::__exit_code!(e),
+					"{}: {e}",
+					$crate::__Context(format_args!($($fmt)+)),
+				);
+			},
 		}
 	};
 }
@@ -0,0 +1,43 @@
+/* Printing a warning with a literal message and a static program name doesn’t
+   allocate, once the buffer it is rendered into has grown large enough.  The
+   allocations are counted in a child process, so that nothing else runs in
+   between. */
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::in_child;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		unsafe { System.alloc(layout) }
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		unsafe { System.dealloc(ptr, layout) }
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+	let Some(out) = in_child("literal", || {
+		cerm::set_static_progname("prog");
+		cerm::warn!("disk almost full");
+		let n = ALLOCATIONS.load(Ordering::Relaxed);
+		cerm::warn!("disk almost full");
+		assert_eq!(ALLOCATIONS.load(Ordering::Relaxed) - n, 0);
+	}) else {
+		return;
+	};
+	assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+	assert_eq!(out.stderr, b"prog: disk almost full\nprog: disk almost full\n");
+}
@@ -0,0 +1,52 @@
+/* Helpers for the tests which need a process of their own, such as those
+   checking how the process dies or what reaches its standard streams.  The
+   test binary runs itself again, with CERM_CHILD naming the case to run.  The
+   child is named “prog”, which without the “full” feature is done through
+   its first argument, on Unix only. */
+
+use std::env;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command, Output, Stdio};
+
+const CHILD: &str = "CERM_CHILD";
+
+/* Run f in a child process and return the output of the child, or None in
+   the child process itself.  In the child, the process exits once f returns,
+   and the other cases do nothing. */
+pub fn in_child(case: &str, f: impl FnOnce()) -> Option<Output> {
+	in_child_with(case, |_| (), f)
+}
+
+/* The same thing as in_child(), but with the command running the child given
+   to setup first */
+pub fn in_child_with(
+	case: &str,
+	setup: impl FnOnce(&mut Command),
+	f: impl FnOnce(),
+) -> Option<Output> {
+	match env::var(CHILD) {
+		Ok(c) if c == case => {
+			#[cfg(feature = "full")]
+			{
+				cerm::set_progname("prog");
+				/* The panic_exit feature changes the default */
+				cerm::set_exit_behavior(cerm::ExitBehavior::Exit);
+			}
+			f();
+			process::exit(0);
+		}
+		Ok(_) => None,
+		Err(_) => {
+			let mut cmd = Command::new(env::current_exe().unwrap());
+			cmd.env(CHILD, case)
+				.stdin(Stdio::null())
+				.stdout(Stdio::piped())
+				.stderr(Stdio::piped());
+			#[cfg(unix)]
+			cmd.arg0("prog");
+			setup(&mut cmd);
+			Some(cmd.output().unwrap())
+		}
+	}
+}
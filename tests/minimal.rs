@@ -0,0 +1,113 @@
+/* Tests of the behaviour which doesn’t depend on the “full” feature, each
+   running with in_child() once per case.  The doctests mostly check what is printed by capturing it, which needs the
+   “full” feature, so these check it from the outside instead and also run
+   without it.  The program name can only be set on Unix without the “full”
+   feature. */
+
+mod common;
+
+#[cfg(any(unix, feature = "full"))]
+mod cases {
+	use std::io;
+
+	use super::common::in_child;
+
+	/* Warnings go to the standard error, and the program carries on */
+	pub fn warn() {
+		let Some(out) = in_child("warn", || {
+			cerm::warn!("disk almost full");
+			cerm::warn!("{} disks almost full", 2);
+		}) else {
+			return;
+		};
+		assert!(out.status.success());
+		assert_eq!(out.stderr, b"prog: disk almost full\nprog: 2 disks almost full\n");
+	}
+
+	/* Notices go to the standard output */
+	pub fn outln() {
+		let Some(out) = in_child("outln", || cerm::outln!("{} files removed", 3)) else {
+			return;
+		};
+		assert!(out.status.success());
+		assert_eq!(out.stdout, b"prog: 3 files removed\n");
+		assert!(out.stderr.is_empty());
+	}
+
+	/* Fatal diagnostics exit with the given code */
+	pub fn err_code() {
+		let Some(out) = in_child("err_code", || cerm::err_code!(3, "disk {}", "full")) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(3));
+		assert_eq!(out.stderr, b"prog: disk full\n");
+	}
+
+	/* require!() prints the error of a Result */
+	pub fn require_result() {
+		let Some(out) = in_child("require_result", || {
+			let n = cerm::require!(Ok::<_, io::Error>(1));
+			cerm::require!(Err::<(), _>(io::Error::other(format!("{n} is too few"))));
+		}) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(1));
+		assert_eq!(out.stderr, b"prog: 1 is too few\n");
+	}
+
+	/* require!() prints the given message for an Option */
+	pub fn require_option() {
+		let Some(out) = in_child("require_option", || {
+			cerm::require!(None::<i32>, "no {} given", "input");
+		}) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(1));
+		assert_eq!(out.stderr, b"prog: no input given\n");
+	}
+
+	/* The path comes before the message */
+	pub fn err_file() {
+		let Some(out) = in_child("err_file", || cerm::err_file!("in.txt", "file is empty")) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(1));
+		assert_eq!(out.stderr, b"prog: in.txt: file is empty\n");
+	}
+
+	/* exit_quiet!() prints nothing */
+	pub fn exit_quiet() {
+		let Some(out) = in_child("exit_quiet", || cerm::exit_quiet!(4)) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(4));
+		assert!(out.stderr.is_empty());
+	}
+
+	/* An empty message is pointed out in debug builds */
+	pub fn empty_message() {
+		let Some(out) = in_child("empty_message", || cerm::err!("{}", "")) else {
+			return;
+		};
+		assert_eq!(out.status.code(), Some(1));
+		if cfg!(debug_assertions) {
+			assert_eq!(out.stderr, b"prog: fatal diagnostic has an empty message\nprog: \n");
+		} else {
+			assert_eq!(out.stderr, b"prog: \n");
+		}
+	}
+}
+
+fn main() {
+	#[cfg(any(unix, feature = "full"))]
+	{
+		cases::warn();
+		cases::outln();
+		cases::err_code();
+		cases::require_result();
+		cases::require_option();
+		cases::err_file();
+		cases::exit_quiet();
+		cases::empty_message();
+	}
+}
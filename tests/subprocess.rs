@@ -0,0 +1,166 @@
+/* Tests which run in a child process.  Every case runs the code under test
+   with in_child(), once per case, and is called from main() in the parent and
+   the child alike. */
+
+mod common;
+
+use std::io::{self, Write};
+use std::panic;
+use std::process::Command;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+
+use common::{in_child, in_child_with};
+
+/* set_abort_on_error() dies from SIGABRT, after running the flush hook */
+#[cfg(unix)]
+fn abort_on_error() {
+	let Some(out) = in_child("abort_on_error", || {
+		cerm::set_abort_on_error(true);
+		cerm::set_async_flush_hook(Box::new(|| eprintln!("flushed")));
+		cerm::err_code!(3, "dumping core");
+	}) else {
+		return;
+	};
+	assert_eq!(out.status.code(), None);
+	assert_eq!(out.status.signal(), Some(6));
+	assert_eq!(out.stderr, b"prog: dumping core\nflushed\n");
+}
+
+/* A failing local sink falls back to the standard error, saying so once */
+fn failing_sink() {
+	struct Full;
+
+	impl Write for Full {
+		fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+			Err(io::Error::from(io::ErrorKind::StorageFull))
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let Some(out) = in_child("failing_sink", || {
+		cerm::set_local_sink(Full);
+		cerm::warn!("this is printed to the standard error");
+		cerm::warn!("and so is this");
+	}) else {
+		return;
+	};
+	assert_eq!(
+		String::from_utf8(out.stderr).unwrap(),
+		"prog: writing to diagnostic sink failed: no storage space\n\
+		 prog: this is printed to the standard error\n\
+		 prog: and so is this\n",
+	);
+	assert!(out.stdout.is_empty());
+}
+
+/* A fatal diagnostic raised while printing another exits with
+   REENTRANT_EXIT_CODE instead of recursing */
+fn reentrant_err() {
+	struct Broken;
+
+	impl Write for Broken {
+		fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+			panic!("sink is broken");
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let Some(out) = in_child("reentrant_err", || {
+		/* The panic hook reports panics with err!(), which would recurse */
+		panic::set_hook(Box::new(|info| cerm::err!("{info}")));
+		cerm::set_local_sink(Broken);
+		cerm::err!("something went wrong");
+	}) else {
+		return;
+	};
+	assert_eq!(out.status.code(), Some(cerm::REENTRANT_EXIT_CODE));
+	let stderr = String::from_utf8(out.stderr).unwrap();
+	assert!(stderr.starts_with("prog: panicked at "), "{stderr}");
+	assert!(stderr.ends_with("\nsink is broken\n"), "{stderr}");
+}
+
+/* The alert is written with the diagnostic, and only to a terminal */
+fn alert_on_error() {
+	let Some(out) = in_child("alert_on_error", || {
+		cerm::__assume_terminal();
+		cerm::set_alert_on_error(cerm::AlertStyle::Bell);
+		cerm::err!("disk full");
+	}) else {
+		return;
+	};
+	assert_eq!(out.status.code(), Some(1));
+	assert_eq!(out.stderr, b"\x07prog: disk full\n");
+}
+
+/* Nor is the alert written when diagnostics go elsewhere */
+fn alert_on_error_stdout() {
+	let Some(out) = in_child("alert_on_error_stdout", || {
+		cerm::__assume_terminal();
+		cerm::set_alert_on_error(cerm::AlertStyle::Bell);
+		cerm::set_all_to_stdout(true);
+		cerm::err!("disk full");
+	}) else {
+		return;
+	};
+	assert_eq!(out.stdout, b"prog: disk full\n");
+}
+
+/* set_all_to_stdout() sends diagnostics to the standard output only */
+fn all_to_stdout() {
+	let Some(out) = in_child("all_to_stdout", || {
+		cerm::set_all_to_stdout(true);
+		cerm::warn!("this is printed to the standard output");
+	}) else {
+		return;
+	};
+	assert_eq!(out.stdout, b"prog: this is printed to the standard output\n");
+	assert!(out.stderr.is_empty());
+}
+
+/* outln!() writes to the standard output only */
+fn outln() {
+	let Some(out) = in_child("outln", || cerm::outln!("binary file matches")) else {
+		return;
+	};
+	assert_eq!(out.stdout, b"prog: binary file matches\n");
+	assert!(out.stderr.is_empty());
+}
+
+/* Diagnostics written to a closed pipe are dropped without panicking */
+fn closed_stdout() {
+	let setup = |cmd: &mut Command| {
+		let (r, w) = io::pipe().unwrap();
+		drop(r);
+		cmd.stdout(w);
+	};
+	let Some(out) = in_child_with("closed_stdout", setup, || {
+		cerm::set_all_to_stdout(true);
+		cerm::warn!("nobody reads this");
+		cerm::outln!("nor this");
+		cerm::set_all_to_stdout(false);
+		cerm::warn!("still running");
+	}) else {
+		return;
+	};
+	assert!(out.status.success());
+	assert_eq!(out.stderr, b"prog: still running\n");
+}
+
+fn main() {
+	#[cfg(unix)]
+	abort_on_error();
+	failing_sink();
+	reentrant_err();
+	alert_on_error();
+	alert_on_error_stdout();
+	all_to_stdout();
+	outln();
+	closed_stdout();
+}